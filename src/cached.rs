@@ -1,8 +1,15 @@
 pub use self::args::CachedArgs;
+pub use self::cache::cache_clear;
+pub use self::cache::cache_gc;
+pub use self::cache::cache_stats;
 pub use self::cache::cached;
 pub use self::cache::CacheStatus;
+pub use self::cache::OutputStream;
 pub use self::handle::handle_cached;
 
 mod args;
 mod cache;
+mod file_key;
 mod handle;
+mod shared;
+mod store;
@@ -1,3 +1,4 @@
+use ::std::path::PathBuf;
 use ::std::time::Duration;
 
 use ::clap::Parser;
@@ -8,15 +9,21 @@ use crate::common::CommandArgs;
 #[derive(Parser, Debug, PartialEq)]
 #[command(
     name = "cached",
-    about = "Cache the output of a command for a given duration, running it only if there is no cache or it has expired. Stderr is only shown on first run."
+    about = "Cache the output of a command for a given duration, running it only if there is no cache or it has expired."
 )]
 pub struct CachedArgs {
     /// Duration after which the cache should be invalidated, e.g. "30 min" or "1 day -1 hour".
     #[arg(value_parser = parse_dur, short = 'd', long = "duration", default_value = "15 min")]
     pub duration: Duration,
+    /// Also cache failed runs (non-zero exit code), for this duration, e.g. "5 min". They are replayed with the same exit code.
+    #[arg(value_parser = parse_dur, long)]
+    pub cache_failures: Option<Duration>,
+    /// After --duration, keep returning the expired output for this much longer (e.g. "1 day"), while refreshing it in the background.
+    #[arg(value_parser = parse_dur, long)]
+    pub serve_stale: Option<Duration>,
     #[clap(flatten)]
     pub key: CachedKeyArgs,
-    /// When loading from cache, do not show the previous output.
+    /// When loading from cache (or from another process running the same command), do not show the previous output.
     #[arg(short = 's', long)]
     pub no_cached_output: bool,
     /// Use exit code 0 if the command is cached, and exit code 255 if it ran successfully.
@@ -25,8 +32,23 @@ pub struct CachedArgs {
     /// Print extra information, e.g. whether the command was run or not.
     #[arg(short = 'v', long)]
     pub verbose: bool,
+    #[arg(long)]
+    /// Only show the commands that would run, and why, without running them. Can also set RUSHT_DRY_RUN.
+    pub dry_run: bool,
+    /// Maximum total size of all cached outputs, e.g. "200M" or "2G". Least recently used entries are removed beyond this.
+    #[arg(value_parser = parse_size, long, default_value = "500M")]
+    pub max_size: u64,
+    /// Instead of running a command, remove entries that expired (by the duration they were cached for), as well as corrupt and leftover files.
+    #[arg(long, conflicts_with_all = ["stats", "clear"])]
+    pub gc: bool,
+    /// Instead of running a command, show the number and size of cache entries (and how many expired).
+    #[arg(long, conflicts_with = "clear")]
+    pub stats: bool,
+    /// Instead of running a command, remove all cache entries.
+    #[arg(long)]
+    pub clear: bool,
     #[command(subcommand)]
-    pub cmd: CommandArgs,
+    pub cmd: Option<CommandArgs>,
 }
 
 #[derive(Parser, Debug, PartialEq, Default)]
@@ -53,6 +75,15 @@ pub struct CachedKeyArgs {
     /// Name of an environment variable. Invalidates cache if the value changes.
     #[arg(short = 'e', long)]
     pub env: Vec<String>,
+    /// Glob of files (relative to the working dir) that invalidate the cache when they change, e.g. 'src/**'. Can be repeated; a later '!src/gen/**' excludes files. Skips files ignored by git.
+    #[arg(short = 'f', long)]
+    pub file: Vec<String>,
+    /// Directory whose files invalidate the cache when they change. Skips files ignored by git.
+    #[arg(long)]
+    pub dir: Vec<PathBuf>,
+    /// For --file and --dir, compare modification time and size instead of content. Faster, but may invalidate needlessly.
+    #[arg(long)]
+    pub file_mtime: bool,
     /// Just a text. Invalidates cache if a different text is passed.
     #[arg(short = 't', long)]
     pub text: Vec<String>,
@@ -69,9 +100,15 @@ pub struct CachedKeyArgs {
 
 
 impl CachedArgs {
+    /// Manage the cache instead of running a command.
+    pub fn is_management(&self) -> bool {
+        self.gc || self.stats || self.clear
+    }
+
     pub fn any_explicit_key(&self) -> bool {
         self.key.git_head || self.key.git_head_diff || self.key.git_base || self.key.git_repo_dir ||
-            self.key.git_worktree || self.key.git_pending || !self.key.env.is_empty() || !self.key.text.is_empty()
+            self.key.git_worktree || self.key.git_pending || !self.key.env.is_empty() || !self.key.text.is_empty() ||
+            !self.key.file.is_empty() || !self.key.dir.is_empty()
     }
 }
 
@@ -79,6 +116,8 @@ impl Default for CachedArgs {
     fn default() -> Self {
         CachedArgs {
             duration: Duration::from_secs(15 * 60),
+            cache_failures: None,
+            serve_stale: None,
             key: CachedKeyArgs {
                 git_head: false,
                 git_base: false,
@@ -87,6 +126,9 @@ impl Default for CachedArgs {
                 git_worktree: false,
                 git_pending: false,
                 env: vec![],
+                file: vec![],
+                dir: vec![],
+                file_mtime: false,
                 text: vec![],
                 no_dir: false,
                 no_command: false,
@@ -95,17 +137,38 @@ impl Default for CachedArgs {
             no_cached_output: false,
             exit_code: false,
             verbose: false,
-            cmd: CommandArgs::Cmd(Vec::new()),
+            dry_run: false,
+            max_size: 500 * 1024 * 1024,
+            gc: false,
+            stats: false,
+            clear: false,
+            cmd: Some(CommandArgs::Cmd(Vec::new())),
         }
     }
 }
 
+/// Parse a size in bytes, with an optional K, M or G suffix (powers of 1024).
+fn parse_size(txt: &str) -> Result<u64, String> {
+    let txt = txt.trim();
+    let upper = txt.to_ascii_uppercase();
+    let number = upper.strip_suffix('B').unwrap_or(&upper);
+    let (number, factor) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1024),
+        Some('M') => (&number[..number.len() - 1], 1024 * 1024),
+        Some('G') => (&number[..number.len() - 1], 1024 * 1024 * 1024),
+        _ => (number, 1),
+    };
+    let nr = number.trim().parse::<u64>()
+        .map_err(|err| format!("could not parse size '{}', expected e.g. 500M, err '{}'", txt, err))?;
+    nr.checked_mul(factor).ok_or_else(|| format!("size '{}' is too large", txt))
+}
+
 #[test]
 fn test_cli_args() {
     let mut args = CachedArgs::try_parse_from(&["cmd", "ls"]).unwrap();
     assert!(!args.any_explicit_key());
     assert_eq!(args, CachedArgs {
-        cmd: CommandArgs::Cmd(vec!["ls".to_owned()]),
+        cmd: Some(CommandArgs::Cmd(vec!["ls".to_owned()])),
         ..CachedArgs::default()
     });
     args = CachedArgs::try_parse_from(&["cmd", "--duration", "1 year", "ls"]).unwrap();
@@ -116,6 +179,8 @@ fn test_cli_args() {
     assert!(args.any_explicit_key());
     args = CachedArgs::try_parse_from(&["cmd", "-d1y", "-gpe", "ENV_VAR", "-CDEt", "string", "-t", "another string", "--", "ls"]).unwrap();
     assert!(args.any_explicit_key());
+    args = CachedArgs::try_parse_from(&["cmd", "--dry-run", "ls"]).unwrap();
+    assert!(args.dry_run);
     args = CachedArgs::try_parse_from(&["cmd", "-D", "--git-worktree", "ls"]).unwrap();
     assert!(args.any_explicit_key());
     assert!(args.key.git_worktree);
@@ -123,3 +188,33 @@ fn test_cli_args() {
     assert!(CachedArgs::try_parse_from(&["cmd", "--git-worktree", "ls"]).is_err());
     assert!(CachedArgs::try_parse_from(&["cmd", "-D", "--git-worktree", "--git-repo-dir", "ls"]).is_err());
 }
+
+#[test]
+fn test_management_args() {
+    let args = CachedArgs::try_parse_from(&["cmd", "--gc", "-d", "2h", "--max-size", "1G"]).unwrap();
+    assert!(args.is_management());
+    assert_eq!(args.cmd, None);
+    assert_eq!(args.max_size, 1024 * 1024 * 1024);
+    assert!(CachedArgs::try_parse_from(&["cmd", "--stats", "--clear"]).is_err());
+    let args = CachedArgs::try_parse_from(&["cmd", "--cache-failures", "5m", "ls"]).unwrap();
+    assert_eq!(args.cache_failures, Some(Duration::from_secs(300)));
+    let args = CachedArgs::try_parse_from(&["cmd", "-d", "10m", "--serve-stale", "2h", "git", "branch", "-r"]).unwrap();
+    assert_eq!(args.serve_stale, Some(Duration::from_secs(7200)));
+}
+
+#[test]
+fn test_file_key_args() {
+    let args = CachedArgs::try_parse_from(&["cmd", "--file", "pom.xml", "-f", "src/**", "--file-mtime", "mvn", "dependency:tree"]).unwrap();
+    assert!(args.any_explicit_key());
+    assert_eq!(args.key.file, vec!["pom.xml".to_owned(), "src/**".to_owned()]);
+    let args = CachedArgs::try_parse_from(&["cmd", "--dir", "src", "-C", "ls"]).unwrap();
+    assert!(args.any_explicit_key());
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("1024"), Ok(1024));
+    assert_eq!(parse_size("2k"), Ok(2048));
+    assert_eq!(parse_size("500MB"), Ok(500 * 1024 * 1024));
+    assert!(parse_size("lots").is_err());
+}
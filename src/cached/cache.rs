@@ -1,154 +1,416 @@
 use ::std::env;
 use ::std::env::VarError;
-use ::std::fs::create_dir_all;
-use ::std::fs::OpenOptions;
-use ::std::io::BufReader;
-use ::std::io::Write;
-use ::std::path::Path;
-use ::std::path::PathBuf;
+use ::std::fs;
+use ::std::process;
+use ::std::process::Command as StdCommand;
+use ::std::process::Stdio;
 use ::std::time::Duration;
 
+use ::async_std::task::sleep;
 use ::log::debug;
+use ::log::warn;
 use ::serde::Deserialize;
 use ::serde::Serialize;
 use ::time::OffsetDateTime;
 
 use crate::cached::args::CachedKeyArgs;
+use crate::cached::file_key::files_key;
+use crate::cached::shared::follow_shared_output;
+use crate::cached::shared::SharedOutput;
+use crate::cached::store::CacheStore;
 use crate::cached::CachedArgs;
-use crate::common::fail;
+use crate::common::DiscardWriter;
+use crate::common::explain;
 use crate::common::file_modified_time_in_seconds;
+use crate::common::format_bytes;
 use crate::common::git::{git_head_ref, git_stripped_diff};
 use crate::common::git::git_master_base_ref;
 use crate::common::git::git_repo_dir;
 use crate::common::git::git_common_dir;
 use crate::common::git::git_uncommitted_changes;
+use crate::common::InterleavedBuffer;
+use crate::common::is_dry_run;
 use crate::common::LineWriter;
 use crate::common::safe_filename;
+use crate::common::skip_for_dry_run;
 use crate::common::Task;
 use crate::common::TeeWriter;
 use crate::common::unique_filename;
-use crate::common::VecWriter;
 use crate::ExitStatus;
 
-pub const DATA_VERSION: u32 = 1;
+/// Increment for breaking changes, to avoid loading old cache files
+pub const DATA_VERSION: u32 = 2;
+
+/// Set for the detached process that refreshes a stale entry.
+static REFRESH_ENV_NAME: &str = "RUSHT_CACHED_REFRESH";
+const REFRESH_LOCK: &str = "refresh";
+const RUN_LOCK: &str = "run";
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CacheStatus {
     RanSuccessfully,
-    FromCache(String),
+    FromCache(Vec<(OutputStream, String)>, ExitStatus),
+    /// Another process was already running the command, this is its output once it finished.
+    Shared(Vec<(OutputStream, String)>, ExitStatus),
     Failed(ExitStatus),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Out,
+    Err,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Freshness {
+    Fresh,
+    /// Expired, but may be served while it is refreshed.
+    Stale,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct Cache {
     time: OffsetDateTime,
     task: Task,
-    output: String,
+    /// Lines of stdout and stderr, in the order they were produced.
+    output: Vec<(OutputStream, String)>,
+    exit_code: u8,
+    /// How long the entry can be used (including serving it stale), according to the run that created it.
+    #[serde(default)]
+    keep_for: Option<Duration>,
 }
 
-pub async fn cached(args: CachedArgs, writer: &mut impl LineWriter) -> Result<CacheStatus, String> {
-    let task = args.cmd.clone().into_task();
-    let cache_pth = get_cache_path(&args, &task).await?;
-    let cached_output = try_read_cache(&args.duration, &cache_pth);
-    if let Some(output) = cached_output {
-        return Ok(CacheStatus::FromCache(output));
+pub async fn cached(
+    args: CachedArgs,
+    out_writer: &mut impl LineWriter,
+    err_writer: &mut impl LineWriter,
+) -> Result<CacheStatus, String> {
+    let Some(cmd) = args.cmd.clone() else {
+        return Err("no command given to run and cache".to_owned());
+    };
+    let task = cmd.into_task();
+    let store = open_store(&args)?;
+    let key = build_key(&args, &task).await?;
+    debug!("cache key {} from args {:?}", key, args);
+    if env::var_os(REFRESH_ENV_NAME).is_some() {
+        return refresh_in_background(&args, task, &store, &key).await;
     }
-    let mut vec_writer = VecWriter::new();
-    let mut tee_writer = TeeWriter::new(writer, &mut vec_writer);
+    let cached_output = try_read_cache(&args.duration, args.cache_failures.as_ref(), args.serve_stale.as_ref(), &store, &key);
+    if let Some((cache, freshness)) = cached_output {
+        explain(format_args!("cached hit at {}, not running {}", store.entry_path(&key).to_string_lossy(), task.as_cmd_str()));
+        if freshness == Freshness::Stale {
+            start_background_refresh(&store, &key, args.verbose);
+        }
+        return Ok(CacheStatus::FromCache(cache.output, ExitStatus::of(cache.exit_code)));
+    }
+    if is_dry_run() {
+        return run_and_cache(&args, task, &store, &key, &SharedOutput::none(), out_writer, err_writer).await;
+    }
+    run_once_for_key(&args, task, &store, &key, out_writer, err_writer).await
+}
+
+/// Run the task, unless another process is already running it for the same key.
+/// In that case, wait for it and return its output and result.
+async fn run_once_for_key(
+    args: &CachedArgs,
+    task: Task,
+    store: &CacheStore,
+    key: &str,
+    out_writer: &mut impl LineWriter,
+    err_writer: &mut impl LineWriter,
+) -> Result<CacheStatus, String> {
+    loop {
+        let lock = match store.try_lock(key, RUN_LOCK) {
+            Ok(lock) => lock,
+            Err(err) => {
+                warn!("cannot share the run with other processes, {}", err);
+                return run_and_cache(args, task, store, key, &SharedOutput::none(), out_writer, err_writer).await;
+            }
+        };
+        if let Some(_lock) = lock {
+            // another process may have finished between reading the cache and getting the lock
+            if let Some((cache, Freshness::Fresh)) = try_read_cache(&args.duration, args.cache_failures.as_ref(), None, store, key) {
+                return Ok(CacheStatus::FromCache(cache.output, ExitStatus::of(cache.exit_code)));
+            }
+            if args.verbose {
+                // not using monitor mode, because its status lines would end up in the cache
+                eprintln!("no valid cache, running {}", task.as_cmd_str());
+            }
+            let shared = SharedOutput::create(&store.shared_output_path(key, process::id()))?;
+            return run_and_cache(args, task, store, key, &shared, out_writer, err_writer).await;
+        }
+        if let Some(pid) = store.lock_holder(key, RUN_LOCK) {
+            if args.verbose {
+                eprintln!("process {} is already running this command, waiting for its output", pid);
+            }
+            let is_running = || store.lock_holder(key, RUN_LOCK) == Some(pid);
+            let shared_pth = store.shared_output_path(key, pid);
+            if let Some((output, status)) = follow_shared_output(shared_pth, is_running).await {
+                return Ok(CacheStatus::Shared(output, status));
+            }
+            if args.verbose {
+                eprintln!("process {} stopped before finishing the command, trying again", pid);
+            }
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+}
+
+async fn run_and_cache(
+    args: &CachedArgs,
+    task: Task,
+    store: &CacheStore,
+    key: &str,
+    shared: &SharedOutput,
+    out_writer: &mut impl LineWriter,
+    err_writer: &mut impl LineWriter,
+) -> Result<CacheStatus, String> {
+    if skip_for_dry_run(&task, false).is_some() {
+        explain(format_args!("not writing cache entry at {}", store.entry_path(key).to_string_lossy()));
+        return Ok(CacheStatus::RanSuccessfully);
+    }
+    let buffer = InterleavedBuffer::new();
     let exit_code = task
-        .execute_with_stdout(args.verbose, &mut tee_writer)
+        .execute_with_stdout_nomonitor(
+            &mut TeeWriter::new(&mut TeeWriter::new(out_writer, &mut buffer.writer("out")), &mut shared.writer(OutputStream::Out)),
+            &mut TeeWriter::new(&mut TeeWriter::new(err_writer, &mut buffer.writer("err")), &mut shared.writer(OutputStream::Err)),
+        )
         .await;
+    let result = store_result(args, task, store, key, &buffer, exit_code).await;
+    shared.finish(exit_code);
+    result
+}
+
+async fn store_result(
+    args: &CachedArgs,
+    task: Task,
+    store: &CacheStore,
+    key: &str,
+    buffer: &InterleavedBuffer,
+    exit_code: ExitStatus,
+) -> Result<CacheStatus, String> {
+    if exit_code.is_err() && args.cache_failures.is_none() {
+        return Ok(CacheStatus::Failed(exit_code));
+    }
+    let output = buffer.take().await
+        .into_iter()
+        .map(|(stream, line)| (if stream == "err" { OutputStream::Err } else { OutputStream::Out }, line))
+        .collect();
+    let max_age = if exit_code.is_ok() { args.duration } else { args.cache_failures.unwrap_or(Duration::ZERO) };
+    let keep_for = max_age + args.serve_stale.unwrap_or(Duration::ZERO);
+    update_cache(output, exit_code, keep_for, task, store, key)?;
     if exit_code.is_err() {
         return Ok(CacheStatus::Failed(exit_code));
     }
-    let output = vec_writer.get().join("\n");
-    update_cache(output, task, &cache_pth);
     Ok(CacheStatus::RanSuccessfully)
 }
 
-fn try_read_cache(max_age: &Duration, cache_pth: &Path) -> Option<String> {
-    let cache = OpenOptions::new()
-        .read(true)
-        .open(cache_pth)
-        .map(BufReader::new)
-        .map(serde_json::from_reader::<_, Cache>);
-    match cache {
-        Ok(Ok(cache)) => {
-            debug!(
-                "found cached entry from {} at {}",
-                &cache.time,
-                cache_pth.to_string_lossy()
-            );
-            let age = OffsetDateTime::now_utc() - cache.time;
-            if &age > max_age {
-                debug!(
-                    "cached entry is too old, {}s > {}s",
-                    &age.whole_seconds(),
-                    &max_age.as_secs()
-                );
-            } else {
-                debug!(
-                    "valid cache ({}s); was created with task: {}",
-                    age.whole_seconds(),
-                    cache.task.as_str()
-                );
-                return Some(cache.output);
-            }
+/// Start a detached copy of this process to update the stale entry, unless one is already running for this key.
+fn start_background_refresh(store: &CacheStore, key: &str, verbose: bool) {
+    if is_dry_run() {
+        explain("entry is stale, would refresh it in the background");
+        return;
+    }
+    if store.is_locked(key, REFRESH_LOCK) {
+        debug!("entry {} is stale, but a refresh is already running", key);
+        return;
+    }
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => {
+            warn!("cannot refresh stale cache entry, could not find current executable, error {}", err);
+            return;
         }
-        Ok(Err(_)) => {
-            fail("failed to parse cache file");
+    };
+    let mut cmd = StdCommand::new(exe);
+    cmd.args(env::args_os().skip(1))
+        .env(REFRESH_ENV_NAME, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // own process group, so that it is not stopped together with the caller, e.g. on ctrl+C
+    #[cfg(unix)]
+    ::std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+    match cmd.spawn() {
+        Ok(child) => {
+            debug!("started background refresh of stale entry {} as process {}", key, child.id());
+            if verbose {
+                eprintln!("cache entry is stale, refreshing it in the background");
+            }
         }
-        Err(_) => {
-            debug!("no cached entry at {}", cache_pth.to_string_lossy());
+        Err(err) => warn!("failed to start background refresh of stale cache entry, error {}", err),
+    }
+}
+
+/// Run as the detached process started by `start_background_refresh`, without output.
+async fn refresh_in_background(args: &CachedArgs, task: Task, store: &CacheStore, key: &str) -> Result<CacheStatus, String> {
+    // the command itself should not see this, in case it uses `cached` too
+    env::remove_var(REFRESH_ENV_NAME);
+    let Some(_lock) = store.try_lock(key, REFRESH_LOCK)? else {
+        debug!("not refreshing {}, another process is already doing it", key);
+        return Ok(CacheStatus::RanSuccessfully);
+    };
+    if let Some((_, Freshness::Fresh)) = try_read_cache(&args.duration, args.cache_failures.as_ref(), None, store, key) {
+        debug!("not refreshing {}, it was already refreshed", key);
+        return Ok(CacheStatus::RanSuccessfully);
+    }
+    run_and_cache(args, task, store, key, &SharedOutput::none(), &mut DiscardWriter::new(), &mut DiscardWriter::new()).await
+}
+
+pub fn open_store(args: &CachedArgs) -> Result<CacheStore, String> {
+    let mut pth = dirs::cache_dir().ok_or_else(|| "failed to find cache directory".to_owned())?;
+    pth.push(format!("cmdcache_v{}", DATA_VERSION));
+    CacheStore::in_dir(pth, args.max_size)
+}
+
+/// Successful entries are valid for `max_age`, failed ones only if failures are cached, for `max_failure_age`.
+/// After that, entries are returned as stale for `serve_stale` longer, if given.
+fn try_read_cache(
+    max_age: &Duration,
+    max_failure_age: Option<&Duration>,
+    serve_stale: Option<&Duration>,
+    store: &CacheStore,
+    key: &str,
+) -> Option<(Cache, Freshness)> {
+    let Some(cache) = store.read::<Cache>(key) else {
+        debug!("no cached entry for {}", key);
+        explain(format_args!("no cached entry at {}, would run", store.entry_path(key).to_string_lossy()));
+        return None;
+    };
+    debug!("found cached entry from {} for {}", &cache.time, key);
+    let max_age = if cache.exit_code == 0 {
+        max_age
+    } else if let Some(max_failure_age) = max_failure_age {
+        max_failure_age
+    } else {
+        debug!("cached entry is for a failed run (code {}), but failures are not cached", cache.exit_code);
+        explain(format_args!("cached entry is a failure (code {}) and --cache-failures is not set, would run again", cache.exit_code));
+        return None;
+    };
+    let age = OffsetDateTime::now_utc() - cache.time;
+    if age > *max_age {
+        if let Some(serve_stale) = serve_stale {
+            if age <= *max_age + *serve_stale {
+                debug!("cached entry is expired, but can be served stale ({}s <= {}s + {}s)",
+                    age.whole_seconds(), max_age.as_secs(), serve_stale.as_secs());
+                explain(format_args!("cached entry expired ({}s > {}s), but serving it stale", age.whole_seconds(), max_age.as_secs()));
+                return Some((cache, Freshness::Stale));
+            }
         }
+        debug!(
+            "cached entry is too old, {}s > {}s",
+            &age.whole_seconds(),
+            &max_age.as_secs()
+        );
+        explain(format_args!("cached entry expired ({}s > {}s), would run again", age.whole_seconds(), max_age.as_secs()));
+        return None;
     }
-    None
+    debug!(
+        "valid cache ({}s); was created with task: {}",
+        age.whole_seconds(),
+        cache.task.as_str()
+    );
+    Some((cache, Freshness::Fresh))
 }
 
-fn update_cache(output: String, task: Task, cache_pth: &Path) {
-    let mut cache_file = OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(cache_pth)
-        .unwrap_or_else(|err| {
-            panic!(
-                "failed to create/open cache file at {}, err {}",
-                cache_pth.to_string_lossy(),
-                err
-            )
-        });
+fn update_cache(
+    output: Vec<(OutputStream, String)>,
+    status: ExitStatus,
+    keep_for: Duration,
+    task: Task,
+    store: &CacheStore,
+    key: &str,
+) -> Result<(), String> {
     let cache = Cache {
         time: OffsetDateTime::now_utc(),
         task,
         output,
+        exit_code: status.code(),
+        keep_for: Some(keep_for),
     };
-    let cache_json = serde_json::to_string(&cache).expect("failed to serialize cache");
-    debug!(
-        "writing output ({} bytes json) to cache at {}",
-        cache_json.len(),
-        cache_pth.to_string_lossy()
-    );
-    cache_file
-        .write_all(cache_json.as_bytes())
-        .unwrap_or_else(|err| {
-            panic!(
-                "failed to write to cache file at {}, err {}",
-                cache_pth.to_string_lossy(),
-                err
-            )
-        });
+    store.write(key, &cache)
 }
 
-async fn get_cache_path(args: &CachedArgs, task: &Task) -> Result<PathBuf, String> {
-    let key = build_key(args, task).await?;
-    let filename = unique_filename(&key);
-    let mut pth = dirs::cache_dir().expect("failed to find cache directory");
-    pth.push(format!("cmdcache_v{}", DATA_VERSION));
-    create_dir_all(&pth).unwrap();
-    pth.push(filename);
-    debug!("created cache path {} from args {:?}", pth.to_string_lossy(), args);
-    Ok(pth)
+/// Remove entries that expired (by the duration they were cached for) or are corrupt, and leftover files,
+/// then apply the size limit.
+pub fn cache_gc(args: &CachedArgs) -> Result<Vec<String>, String> {
+    let store = open_store(args)?;
+    let (expired, corrupt) = remove_expired(&store, OffsetDateTime::now_utc());
+    let abandoned = store.remove_abandoned();
+    let evicted = store.evict_over_limit();
+    let old_versions = remove_old_versions(&store);
+    Ok(vec![format!(
+        "removed {} expired, {} corrupt, {} leftover and {} least recently used entries, and {} old cache version(s); {} remaining",
+        expired, corrupt, abandoned, evicted, old_versions, format_bytes(store.total_size()))])
+}
+
+/// Remove expired and unreadable entries, returning how many of each were removed.
+fn remove_expired(store: &CacheStore, now: OffsetDateTime) -> (usize, usize) {
+    let mut expired = 0;
+    let mut corrupt = 0;
+    for entry in store.entries() {
+        match store.peek::<Cache>(&entry.key) {
+            Some(cache) => {
+                if cache.is_expired(now) && store.remove(&entry.key) {
+                    expired += 1;
+                }
+            }
+            None => {
+                if store.remove(&entry.key) {
+                    corrupt += 1;
+                }
+            }
+        }
+    }
+    (expired, corrupt)
+}
+
+impl Cache {
+    /// Whether no run would use this entry anymore. Unknown for entries that do not record it, those are kept.
+    fn is_expired(&self, now: OffsetDateTime) -> bool {
+        self.keep_for.is_some_and(|keep_for| now - self.time > keep_for)
+    }
+}
+
+/// Remove cache directories of older data versions, which are never read anymore.
+fn remove_old_versions(store: &CacheStore) -> usize {
+    let Some(parent) = store.dir().parent() else {
+        return 0;
+    };
+    (1..DATA_VERSION)
+        .map(|version| parent.join(format!("cmdcache_v{}", version)))
+        .filter(|dir| dir.is_dir())
+        .filter(|dir| match fs::remove_dir_all(dir) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("failed to remove old cache directory {}, error {}", dir.to_string_lossy(), err);
+                false
+            }
+        })
+        .count()
+}
+
+pub fn cache_stats(args: &CachedArgs) -> Result<Vec<String>, String> {
+    let store = open_store(args)?;
+    let entries = store.entries();
+    let now = OffsetDateTime::now_utc();
+    let size: u64 = entries.iter().map(|entry| entry.size).sum();
+    let expired = entries.iter()
+        .filter(|entry| store.peek::<Cache>(&entry.key)
+            .is_none_or(|cache| cache.is_expired(now)))
+        .count();
+    Ok(vec![
+        format!("location: {}", store.dir().to_string_lossy()),
+        format!("entries: {} ({} expired or unreadable)", entries.len(), expired),
+        format!("size: {} of {} limit", format_bytes(size), format_bytes(store.max_size())),
+    ])
+}
+
+pub fn cache_clear(args: &CachedArgs) -> Result<Vec<String>, String> {
+    let store = open_store(args)?;
+    let removed = store.clear();
+    Ok(vec![format!("removed {} cache entries from {}", removed, store.dir().to_string_lossy())])
 }
 
 async fn build_key(args: &CachedArgs, task: &Task) -> Result<String, String> {
@@ -207,6 +469,9 @@ async fn build_key_with(
     for env_key in &args.env {
         key.push(get_from_env(env_key)?)
     }
+    if !args.file.is_empty() || !args.dir.is_empty() {
+        key.push(files_key(&task.working_dir, &args.file, &args.dir, args.file_mtime)?)
+    }
     for text in &args.text {
         key.push(text.to_owned())
     }
@@ -230,6 +495,7 @@ async fn add_pending_file_timestamps(task: &&Task, key: &mut Vec<String>) -> Res
 #[cfg(test)]
 mod tests {
     use ::std::collections::HashMap;
+    use ::std::path::PathBuf;
 
     use super::*;
 
@@ -240,6 +506,8 @@ mod tests {
             working_dir: PathBuf::from("/tmp"),
             extra_envs: HashMap::new(),
             stdin: None,
+            timeout: None,
+            process_group: None,
         }
     }
 
@@ -250,6 +518,57 @@ mod tests {
         })
     }
 
+    #[test]
+    fn failures_only_read_if_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().to_owned(), 1024 * 1024).unwrap();
+        let output = vec![
+            (OutputStream::Out, "compiling".to_owned()),
+            (OutputStream::Err, "error: missing semicolon".to_owned()),
+        ];
+        update_cache(output.clone(), ExitStatus::of(2), Duration::from_secs(3600), create_test_task(), &store, "key").unwrap();
+        let hour = Duration::from_secs(3600);
+        assert_eq!(try_read_cache(&hour, None, None, &store, "key"), None);
+        let (cache, freshness) = try_read_cache(&hour, Some(&hour), None, &store, "key").unwrap();
+        assert_eq!(cache.output, output);
+        assert_eq!(cache.exit_code, 2);
+        assert_eq!(freshness, Freshness::Fresh);
+        assert_eq!(try_read_cache(&hour, Some(&Duration::ZERO), None, &store, "key"), None);
+    }
+
+    #[test]
+    fn stale_served_within_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().to_owned(), 1024 * 1024).unwrap();
+        let cache = Cache {
+            time: OffsetDateTime::now_utc() - Duration::from_secs(120),
+            task: create_test_task(),
+            output: vec![(OutputStream::Out, "main".to_owned())],
+            exit_code: 0,
+            keep_for: None,
+        };
+        store.write("key", &cache).unwrap();
+        let minute = Duration::from_secs(60);
+        assert_eq!(try_read_cache(&minute, None, None, &store, "key"), None);
+        let (_, freshness) = try_read_cache(&minute, None, Some(&(minute * 5)), &store, "key").unwrap();
+        assert_eq!(freshness, Freshness::Stale);
+        assert_eq!(try_read_cache(&minute, None, Some(&(minute / 2)), &store, "key"), None);
+    }
+
+    #[test]
+    fn gc_uses_entry_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().to_owned(), 1024 * 1024).unwrap();
+        let day = Duration::from_secs(24 * 3600);
+        update_cache(vec![], ExitStatus::ok(), day, create_test_task(), &store, "day").unwrap();
+        update_cache(vec![], ExitStatus::of(1), Duration::from_secs(60), create_test_task(), &store, "failure").unwrap();
+        fs::write(store.entry_path("corrupt"), "{").unwrap();
+        let now = OffsetDateTime::now_utc();
+        assert_eq!(remove_expired(&store, now + Duration::from_secs(3600)), (1, 1));
+        assert!(store.entry_path("day").exists());
+        assert_eq!(remove_expired(&store, now + day * 2), (1, 0));
+    }
+
     #[async_std::test]
     async fn build_key_vanilla() {
         let task = create_test_task();
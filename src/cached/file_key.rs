@@ -0,0 +1,182 @@
+use ::std::collections::BTreeSet;
+use ::std::fs::File;
+use ::std::io;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::slice;
+use ::std::time::SystemTime;
+
+use ::base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ::base64::Engine;
+use ::ignore::overrides::Override;
+use ::ignore::overrides::OverrideBuilder;
+use ::ignore::WalkBuilder;
+use ::log::debug;
+use ::log::warn;
+use ::sha2::Digest;
+use ::sha2::Sha256;
+
+/// Hash of the files matching the globs (relative to `root`) or inside the directories, skipping files ignored by git.
+/// Uses file content, or modification time and size if `mtime_only`.
+pub fn files_key(root: &Path, globs: &[String], dirs: &[PathBuf], mtime_only: bool) -> Result<String, String> {
+    let mut files = BTreeSet::new();
+    if !globs.is_empty() {
+        files.extend(matching_files(root, globs)?);
+    }
+    for dir in dirs {
+        let dir = root.join(dir);
+        if !dir.is_dir() {
+            return Err(format!("cannot use '{}' for cache key, it is not a directory", dir.to_string_lossy()));
+        }
+        files.extend(walk_files(&dir));
+    }
+    debug!("cache key includes {} files (mtime only: {})", files.len(), mtime_only);
+    let mut hasher = Sha256::new();
+    for pth in &files {
+        hasher.update(pth.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        if mtime_only {
+            let meta = pth.metadata()
+                .map_err(|err| format!("failed to read metadata of '{}' for cache key, error {}", pth.to_string_lossy(), err))?;
+            let modified = meta.modified().ok()
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|dur| dur.as_nanos())
+                .unwrap_or(0);
+            hasher.update(format!("{}_{}", modified, meta.len()).as_bytes());
+        } else {
+            let mut file = File::open(pth)
+                .map_err(|err| format!("failed to open '{}' for cache key, error {}", pth.to_string_lossy(), err))?;
+            io::copy(&mut file, &mut hasher)
+                .map_err(|err| format!("failed to read '{}' for cache key, error {}", pth.to_string_lossy(), err))?;
+        }
+        hasher.update([0]);
+    }
+    Ok(URL_SAFE_NO_PAD.encode(hasher.finalize()))
+}
+
+/// Files matching the globs. Later globs take precedence, so a negation like '!src/gen/**' should come after 'src/**'.
+/// Only walks the directories that the positive globs start with, not the whole tree.
+fn matching_files(root: &Path, globs: &[String]) -> Result<BTreeSet<PathBuf>, String> {
+    let matcher = build_matcher(root, globs)?;
+    let mut files = BTreeSet::new();
+    for base in walk_bases(globs) {
+        for pth in walk_files(&root.join(base)) {
+            let rel = pth.strip_prefix(root).unwrap_or(&pth);
+            if matcher.matched(rel, false).is_whitelist() {
+                files.insert(pth);
+            }
+        }
+    }
+    for glob in globs.iter().filter(|glob| !glob.starts_with('!')) {
+        let single = build_matcher(root, slice::from_ref(glob))?;
+        if !files.iter().any(|pth| single.matched(pth.strip_prefix(root).unwrap_or(pth), false).is_whitelist()) {
+            warn!("file pattern '{}' for cache key does not match any files in '{}'", glob, root.to_string_lossy());
+        }
+    }
+    Ok(files)
+}
+
+/// Globs are relative to `root`, also those without a slash (unlike gitignore, where 'pom.xml' would match at any depth).
+fn build_matcher(root: &Path, globs: &[String]) -> Result<Override, String> {
+    let mut builder = OverrideBuilder::new(root);
+    for glob in globs {
+        let anchored = match glob.strip_prefix('!') {
+            Some(negated) => format!("!/{}", negated.trim_start_matches('/')),
+            None => format!("/{}", glob.trim_start_matches('/')),
+        };
+        builder.add(&anchored).map_err(|err| format!("invalid file pattern '{}', error {}", glob, err))?;
+    }
+    builder.build().map_err(|err| format!("invalid file patterns, error {}", err))
+}
+
+/// The literal leading directories of the positive globs, e.g. 'src/main' for 'src/main/**/*.java'.
+fn walk_bases(globs: &[String]) -> Vec<PathBuf> {
+    let mut bases = globs.iter()
+        .filter(|glob| !glob.starts_with('!'))
+        .map(|glob| glob.trim_start_matches('/')
+            .split('/')
+            .take_while(|part| !part.contains(['*', '?', '[', '{', '\\']))
+            .collect::<PathBuf>())
+        .collect::<Vec<_>>();
+    bases.sort();
+    let mut distinct: Vec<PathBuf> = Vec::with_capacity(bases.len());
+    for base in bases {
+        if !distinct.iter().any(|outer| base.starts_with(outer)) {
+            distinct.push(base);
+        }
+    }
+    distinct
+}
+
+fn walk_files(root: &Path) -> Vec<PathBuf> {
+    WalkBuilder::new(root)
+        .hidden(false)
+        .git_ignore(true)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                debug!("skipping file for cache key, err {}", err);
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_some_and(|typ| typ.is_file()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::fs;
+
+    use super::*;
+
+    #[test]
+    fn changes_with_matched_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("pom.xml"), "<project/>").unwrap();
+        fs::write(root.join("src/Main.java"), "class Main {}").unwrap();
+        let globs = vec!["pom.xml".to_owned(), "src/**".to_owned()];
+        let initial = files_key(root, &globs, &[], false).unwrap();
+        fs::write(root.join("README.md"), "not matched").unwrap();
+        fs::write(root.join("src/build.log"), "ignored by git").unwrap();
+        assert_eq!(files_key(root, &globs, &[], false).unwrap(), initial);
+        fs::write(root.join("src/Main.java"), "class Main { int x; }").unwrap();
+        assert_ne!(files_key(root, &globs, &[], false).unwrap(), initial);
+        let by_dir = files_key(root, &[], &[PathBuf::from("src")], true).unwrap();
+        assert_eq!(files_key(root, &[], &[PathBuf::from("src")], true).unwrap(), by_dir);
+        fs::write(root.join("src/Other.java"), "class Other {}").unwrap();
+        assert_ne!(files_key(root, &[], &[PathBuf::from("src")], true).unwrap(), by_dir);
+    }
+
+    #[test]
+    fn hidden_files_and_negation_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join(".mvn")).unwrap();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".mvn/config"), "-T4").unwrap();
+        fs::write(root.join(".git/HEAD"), "ref").unwrap();
+        fs::write(root.join("src/Main.java"), "class Main {}").unwrap();
+        fs::write(root.join("src/gen/Gen.java"), "class Gen {}").unwrap();
+        let files = matching_files(root, &[".mvn/**".to_owned(), "src/**".to_owned(), "!src/gen/**".to_owned()]).unwrap();
+        assert_eq!(files.into_iter().collect::<Vec<_>>(), vec![root.join(".mvn/config"), root.join("src/Main.java")]);
+        let files = matching_files(root, &["**".to_owned()]).unwrap();
+        assert!(!files.contains(&root.join(".git/HEAD")));
+        let files = matching_files(root, &["Main.java".to_owned()]).unwrap();
+        assert!(files.is_empty());
+    }
+
+    #[test]
+    fn walk_only_literal_prefixes() {
+        let globs = ["src/main/**/*.java", "src/**", "pom.xml", "!src/gen/**", "*.xml"].map(str::to_owned);
+        assert_eq!(walk_bases(&globs[..3]), vec![PathBuf::from("pom.xml"), PathBuf::from("src")]);
+        assert_eq!(walk_bases(&globs), vec![PathBuf::from("")]);
+    }
+}
@@ -1,23 +1,41 @@
-use crate::common::{LineWriter, StdWriter};
+use crate::common::{enable_dry_run, LineWriter, StdWriter};
 use crate::ExitStatus;
 
+use super::cache_clear;
+use super::cache_gc;
+use super::cache_stats;
 use super::cached;
 use super::CacheStatus;
 use super::CachedArgs;
+use super::OutputStream;
 
 pub async fn handle_cached(mut args: CachedArgs) -> ExitStatus {
+    if args.dry_run {
+        enable_dry_run();
+    }
     // sorting is needed for key stability, it is validated later only in debug mode
+    // (not for file globs, their order matters for negations, and the matched files are sorted anyway)
     args.key.text.sort();
     args.key.env.sort();
+    args.key.dir.sort();
+    if args.is_management() {
+        return handle_management(&args);
+    }
+    if args.cmd.is_none() {
+        eprintln!("no command given; use --gc, --stats or --clear to manage the cache without a command");
+        return ExitStatus::err()
+    }
     if args.key.no_command && !args.any_explicit_key() {
         eprintln!("if --no-command is used, some other cache flag must be set");
         return ExitStatus::err()
     }
     let verbose = args.verbose;
     let exit_code = args.exit_code;
+    let cache_failures = args.cache_failures.is_some();
     let show_cached_output = !args.no_cached_output;
     let mut writer = StdWriter::stdout();
-    match cached(args, &mut writer).await {
+    let mut err_writer = StdWriter::stderr();
+    match cached(args, &mut writer, &mut err_writer).await {
         Ok(status) => {
             match status {
                 CacheStatus::RanSuccessfully => {
@@ -33,21 +51,39 @@ pub async fn handle_cached(mut args: CachedArgs) -> ExitStatus {
                         ExitStatus::ok()
                     }
                 }
-                CacheStatus::FromCache(out) => {
-                    if show_cached_output && !out.is_empty() {
-                        writer.write_line(out).await;
+                CacheStatus::FromCache(output, status) => {
+                    if show_cached_output {
+                        write_output(output, &mut writer, &mut err_writer).await;
                     }
                     if verbose {
-                        eprintln!("loaded from cache")
-                        //TODO @mverleg: better msg?
+                        if status.is_ok() {
+                            eprintln!("loaded from cache")
+                        } else {
+                            eprintln!("loaded failed run from cache (code: {})", status)
+                        }
                     }
-                    ExitStatus::ok()
+                    status
+                }
+                CacheStatus::Shared(output, status) => {
+                    if show_cached_output {
+                        write_output(output, &mut writer, &mut err_writer).await;
+                    }
+                    if verbose {
+                        eprintln!("showed output of another process running the same command (code: {})", status)
+                    }
+                    status
                 }
                 CacheStatus::Failed(exit_code) => {
-                    eprintln!(
-                        "the command ran, but it failed and was not cached (code: {})",
-                        exit_code
-                    );
+                    if cache_failures {
+                        if verbose {
+                            eprintln!("the command ran and failed, the failure was cached (code: {})", exit_code)
+                        }
+                    } else {
+                        eprintln!(
+                            "the command ran, but it failed and was not cached (code: {})",
+                            exit_code
+                        );
+                    }
                     exit_code
                 }
             }
@@ -58,3 +94,34 @@ pub async fn handle_cached(mut args: CachedArgs) -> ExitStatus {
         }
     }
 }
+
+async fn write_output(output: Vec<(OutputStream, String)>, writer: &mut impl LineWriter, err_writer: &mut impl LineWriter) {
+    for (stream, line) in output {
+        match stream {
+            OutputStream::Out => writer.write_line(line).await,
+            OutputStream::Err => err_writer.write_line(line).await,
+        }
+    }
+}
+
+fn handle_management(args: &CachedArgs) -> ExitStatus {
+    let result = if args.clear {
+        cache_clear(args)
+    } else if args.gc {
+        cache_gc(args)
+    } else {
+        cache_stats(args)
+    };
+    match result {
+        Ok(lines) => {
+            for line in lines {
+                println!("{}", line);
+            }
+            ExitStatus::ok()
+        }
+        Err(err) => {
+            eprintln!("failed: {}", err);
+            ExitStatus::err()
+        }
+    }
+}
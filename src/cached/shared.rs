@@ -0,0 +1,180 @@
+use ::std::fs;
+use ::std::fs::File;
+use ::std::io::Read;
+use ::std::io::Write;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::sync::Arc;
+use ::std::sync::Mutex;
+use ::std::time::Duration;
+
+use ::async_std::task::sleep;
+use ::async_trait::async_trait;
+use ::log::debug;
+use ::log::warn;
+use ::serde::Deserialize;
+use ::serde::Serialize;
+
+use crate::cached::OutputStream;
+use crate::common::LineWriter;
+use crate::ExitStatus;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// One line of the output file that processes waiting for the same key follow.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SharedRecord {
+    Line(OutputStream, String),
+    Done(u8),
+}
+
+/// Output of a run, written to a file as it is produced, so that other processes can follow it.
+/// The file is removed when the last copy is dropped; followers that already opened it can still read it.
+#[derive(Debug, Clone)]
+pub struct SharedOutput {
+    file: Option<Arc<SharedFile>>,
+}
+
+#[derive(Debug)]
+struct SharedFile {
+    pth: PathBuf,
+    file: Mutex<File>,
+}
+
+impl SharedOutput {
+    pub fn create(pth: &Path) -> Result<Self, String> {
+        let file = File::create(pth).map_err(|err| {
+            format!(
+                "failed to create shared output file {}, error {}",
+                pth.to_string_lossy(),
+                err
+            )
+        })?;
+        Ok(SharedOutput { file: Some(Arc::new(SharedFile { pth: pth.to_owned(), file: Mutex::new(file) })) })
+    }
+
+    /// Does not share anything.
+    pub fn none() -> Self {
+        SharedOutput { file: None }
+    }
+
+    pub fn writer(&self, stream: OutputStream) -> SharedWriter {
+        SharedWriter { stream, output: self.clone() }
+    }
+
+    /// Tell followers the run is complete. The cache should already be updated at this point.
+    pub fn finish(&self, status: ExitStatus) {
+        self.append(&SharedRecord::Done(status.code()))
+    }
+
+    fn append(&self, record: &SharedRecord) {
+        let Some(shared) = &self.file else {
+            return;
+        };
+        let mut json = serde_json::to_string(record).expect("failed to serialize shared output");
+        json.push('\n');
+        let mut file = shared.file.lock().expect("shared output lock poisoned");
+        if let Err(err) = file.write_all(json.as_bytes()) {
+            warn!("failed to write shared output, processes waiting for it may not see everything, error {}", err);
+        }
+    }
+}
+
+impl Drop for SharedFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.pth) {
+            warn!("failed to remove shared output file {}, error {}", self.pth.to_string_lossy(), err);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SharedWriter {
+    stream: OutputStream,
+    output: SharedOutput,
+}
+
+#[async_trait]
+impl LineWriter for SharedWriter {
+    async fn write_line(&mut self, line: impl AsRef<str> + Send) {
+        self.output.append(&SharedRecord::Line(self.stream, line.as_ref().to_owned()))
+    }
+}
+
+/// Collect the output of another process until that process finishes.
+/// Returns `None` if the process stopped without completing (e.g. it crashed or was killed);
+/// the partial output is then discarded, so that it is not shown before the output of running again.
+pub async fn follow_shared_output(
+    pth: PathBuf,
+    is_running: impl Fn() -> bool,
+) -> Option<(Vec<(OutputStream, String)>, ExitStatus)> {
+    // keep the file open, so it can be read to the end even after the writer removed it
+    let mut file: Option<File> = None;
+    let mut pending: Vec<u8> = vec![];
+    let mut output = vec![];
+    loop {
+        // check before reading, so that anything written before the process stopped is still read
+        let running = is_running();
+        if file.is_none() {
+            file = File::open(&pth).ok();
+        }
+        if let Some(file) = &mut file {
+            if let Err(err) = file.read_to_end(&mut pending) {
+                debug!("failed to read shared output {}, error {}", pth.to_string_lossy(), err)
+            }
+        }
+        while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            match serde_json::from_slice::<SharedRecord>(&line) {
+                Ok(SharedRecord::Line(stream, text)) => output.push((stream, text)),
+                Ok(SharedRecord::Done(code)) => return Some((output, ExitStatus::of(code))),
+                Err(err) => warn!("skipping unreadable shared output line, error {}", err),
+            }
+        }
+        if !running {
+            debug!("process writing {} stopped without finishing", pth.to_string_lossy());
+            return None;
+        }
+        sleep(POLL_INTERVAL).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::sync::atomic::AtomicBool;
+    use ::std::sync::atomic::Ordering;
+
+    use super::*;
+
+    #[async_std::test]
+    async fn follow_until_done() {
+        let dir = tempfile::tempdir().unwrap();
+        let pth = dir.path().join("key.run.1.out");
+        let shared = SharedOutput::create(&pth).unwrap();
+        shared.writer(OutputStream::Out).write_line("building").await;
+        shared.writer(OutputStream::Err).write_line("warning: unused").await;
+        shared.writer(OutputStream::Out).write_line("done").await;
+        shared.finish(ExitStatus::of(3));
+        let (output, status) = follow_shared_output(pth.clone(), || true).await.unwrap();
+        assert_eq!(status, ExitStatus::of(3));
+        drop(shared);
+        assert!(!pth.exists());
+        assert_eq!(output, vec![
+            (OutputStream::Out, "building".to_owned()),
+            (OutputStream::Err, "warning: unused".to_owned()),
+            (OutputStream::Out, "done".to_owned()),
+        ]);
+    }
+
+    #[async_std::test]
+    async fn stops_if_runner_disappears() {
+        let dir = tempfile::tempdir().unwrap();
+        let pth = dir.path().join("key.run.1.out");
+        let shared = SharedOutput::create(&pth).unwrap();
+        shared.writer(OutputStream::Out).write_line("partial").await;
+        let running = AtomicBool::new(true);
+        let status = follow_shared_output(pth, || running.swap(false, Ordering::AcqRel)).await;
+        assert_eq!(status, None);
+    }
+}
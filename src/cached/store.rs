@@ -0,0 +1,458 @@
+use ::std::fs;
+use ::std::fs::create_dir_all;
+use ::std::fs::File;
+use ::std::io;
+use ::std::io::Write;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::process;
+use ::std::time::Duration;
+use ::std::time::SystemTime;
+
+use ::log::debug;
+use ::log::warn;
+use ::serde::de::DeserializeOwned;
+use ::serde::Serialize;
+
+use crate::common::is_process_alive;
+use crate::common::process_start_time;
+
+/// Files that are not entries (e.g. temporary files of crashed writers) are removed by gc after this long.
+const ABANDONED_AFTER: Duration = Duration::from_secs(3600);
+
+/// A lock file without a pid is assumed to be left by a crash after this long, otherwise it may still be written.
+const EMPTY_LOCK_ABANDONED_AFTER: Duration = Duration::from_secs(10);
+
+/// Directory of cache entries, one json file per key, limited in total size by removing least recently used entries.
+#[derive(Debug, Clone)]
+pub struct CacheStore {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+/// Exclusive lock on a cache key for some purpose, held by this process until dropped.
+#[derive(Debug)]
+pub struct KeyLock {
+    pth: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreEntry {
+    pub key: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub last_used: SystemTime,
+}
+
+impl CacheStore {
+    pub fn in_dir(dir: PathBuf, max_size: u64) -> Result<Self, String> {
+        create_dir_all(&dir).map_err(|err| {
+            format!(
+                "failed to create cache directory {}, error {}",
+                dir.to_string_lossy(),
+                err
+            )
+        })?;
+        Ok(CacheStore { dir, max_size })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    pub fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Read the entry, marking it as recently used. Unreadable or corrupt entries are removed and treated as missing.
+    pub fn read<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.load(key, true)
+    }
+
+    /// Like `read`, but without any changes to the store (not marking as used, nor removing if corrupt).
+    pub fn peek<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.load(key, false)
+    }
+
+    fn load<T: DeserializeOwned>(&self, key: &str, mark_used: bool) -> Option<T> {
+        let pth = self.entry_path(key);
+        let content = match fs::read(&pth) {
+            Ok(content) => content,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    warn!("failed to read cache entry {}, error {}", pth.to_string_lossy(), err);
+                }
+                return None;
+            }
+        };
+        match serde_json::from_slice(&content) {
+            Ok(value) => {
+                if !mark_used {
+                    return Some(value);
+                }
+                if let Err(err) = File::options().write(true).open(&pth).and_then(|file| file.set_modified(SystemTime::now())) {
+                    debug!("could not mark cache entry {} as used, error {}", pth.to_string_lossy(), err);
+                }
+                Some(value)
+            }
+            Err(err) => {
+                if mark_used {
+                    warn!("ignoring corrupt cache entry {}, error {}", pth.to_string_lossy(), err);
+                    self.remove(key);
+                }
+                None
+            }
+        }
+    }
+
+    /// Write the entry atomically (so readers never see a partial file), then evict entries beyond the size limit.
+    pub fn write<T: Serialize>(&self, key: &str, value: &T) -> Result<(), String> {
+        let pth = self.entry_path(key);
+        let json = serde_json::to_string(value).map_err(|err| format!("failed to serialize cache entry, error {}", err))?;
+        let tmp_pth = self.dir.join(format!("{}.{}.tmp", key, process::id()));
+        fs::write(&tmp_pth, &json).map_err(|err| {
+            format!(
+                "failed to write cache file {}, error {}",
+                tmp_pth.to_string_lossy(),
+                err
+            )
+        })?;
+        fs::rename(&tmp_pth, &pth).map_err(|err| {
+            let _ = fs::remove_file(&tmp_pth);
+            format!(
+                "failed to move cache file into place at {}, error {}",
+                pth.to_string_lossy(),
+                err
+            )
+        })?;
+        debug!("wrote {} bytes to cache at {}", json.len(), pth.to_string_lossy());
+        self.evict_over_limit();
+        Ok(())
+    }
+
+    pub fn remove(&self, key: &str) -> bool {
+        remove_file(&self.entry_path(key))
+    }
+
+    /// All entries, least recently used first.
+    pub fn entries(&self) -> Vec<StoreEntry> {
+        let mut entries: Vec<StoreEntry> = self.list_files()
+            .into_iter()
+            .filter_map(|(pth, size, last_used)| {
+                let name = pth.file_name()?.to_str()?;
+                let key = name.strip_suffix(".json")?;
+                Some(StoreEntry { key: key.to_owned(), path: pth.clone(), size, last_used })
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.last_used);
+        entries
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.entries().iter().map(|entry| entry.size).sum()
+    }
+
+    /// Remove least recently used entries until the total size is within the limit. Returns the number removed.
+    pub fn evict_over_limit(&self) -> usize {
+        let entries = self.entries();
+        let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+        let mut removed = 0;
+        for entry in entries {
+            if total <= self.max_size {
+                break;
+            }
+            debug!("evicting least recently used cache entry {} ({} bytes)", entry.path.to_string_lossy(), entry.size);
+            if remove_file(&entry.path) {
+                removed += 1;
+            }
+            total = total.saturating_sub(entry.size);
+        }
+        removed
+    }
+
+    pub fn lock_path(&self, key: &str, purpose: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}.lock", key, purpose))
+    }
+
+    /// Take the lock for the key, unless a process that is still running holds it (then `None`).
+    /// Fails if the lock file cannot be created for another reason, e.g. permissions.
+    pub fn try_lock(&self, key: &str, purpose: &str) -> Result<Option<KeyLock>, String> {
+        let pth = self.lock_path(key, purpose);
+        for _ in 0..2 {
+            match File::options().write(true).create_new(true).open(&pth) {
+                Ok(mut file) => {
+                    let pid = process::id();
+                    if let Err(err) = write!(file, "{}\n{}", pid, process_start_time(pid).unwrap_or_default()) {
+                        warn!("failed to write lock file {}, error {}", pth.to_string_lossy(), err);
+                    }
+                    debug!("acquired {} lock at {}", purpose, pth.to_string_lossy());
+                    return Ok(Some(KeyLock { pth }));
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    if is_lock_held(&pth) || !self.remove_abandoned_lock(key, purpose) {
+                        debug!("{} lock at {} is held by another process", purpose, pth.to_string_lossy());
+                        return Ok(None);
+                    }
+                }
+                Err(err) => {
+                    return Err(format!("failed to create lock file {}, error {}", pth.to_string_lossy(), err));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove the lock if it is still abandoned, returning whether it is gone.
+    /// Another process may replace an abandoned lock at the same time, so it is checked again while
+    /// holding a guard, to not remove the new lock of that other process.
+    fn remove_abandoned_lock(&self, key: &str, purpose: &str) -> bool {
+        let pth = self.lock_path(key, purpose);
+        let guard_pth = self.dir.join(format!("{}.{}.evict", key, purpose));
+        if File::options().write(true).create_new(true).open(&guard_pth).is_err() {
+            if is_old(&guard_pth, EMPTY_LOCK_ABANDONED_AFTER) {
+                debug!("removing abandoned guard at {}", guard_pth.to_string_lossy());
+                remove_file(&guard_pth);
+            }
+            return false;
+        }
+        let is_removed = if is_lock_held(&pth) {
+            false
+        } else {
+            debug!("removing abandoned {} lock at {}", purpose, pth.to_string_lossy());
+            remove_file(&pth);
+            true
+        };
+        remove_file(&guard_pth);
+        is_removed
+    }
+
+    pub fn is_locked(&self, key: &str, purpose: &str) -> bool {
+        is_lock_held(&self.lock_path(key, purpose))
+    }
+
+    /// Process id of the running process that holds the lock, if any.
+    pub fn lock_holder(&self, key: &str, purpose: &str) -> Option<u32> {
+        let content = fs::read_to_string(self.lock_path(key, purpose)).ok()?;
+        running_lock_holder(&content)
+    }
+
+    /// File where the process with this id writes the output of a run for the key, while it is running.
+    /// The process removes it when done, but it may be left behind if the process crashes.
+    pub fn shared_output_path(&self, key: &str, pid: u32) -> PathBuf {
+        self.dir.join(format!("{}.run.{}.out", key, pid))
+    }
+
+    /// Remove files that are not entries and have not been touched for a while, like temporary files left by crashes,
+    /// as well as locks and run output of processes that are no longer running.
+    pub fn remove_abandoned(&self) -> usize {
+        let now = SystemTime::now();
+        self.list_files()
+            .into_iter()
+            .filter(|(pth, _, modified)| {
+                let ext = pth.extension();
+                if ext.is_some_and(|ext| ext == "json") {
+                    return false;
+                }
+                if ext.is_some_and(|ext| ext == "lock") {
+                    return !is_lock_held(pth);
+                }
+                if let Some(pid) = shared_output_pid(pth) {
+                    return !is_process_alive(pid);
+                }
+                now.duration_since(*modified).unwrap_or(Duration::ZERO) > ABANDONED_AFTER
+            })
+            .filter(|(pth, _, _)| remove_file(pth))
+            .count()
+    }
+
+    /// Remove all entries (and leftover files), returning how many entries there were.
+    pub fn clear(&self) -> usize {
+        let removed = self.entries()
+            .into_iter()
+            .filter(|entry| remove_file(&entry.path))
+            .count();
+        self.remove_abandoned();
+        removed
+    }
+
+    fn list_files(&self) -> Vec<(PathBuf, u64, SystemTime)> {
+        let listing = match fs::read_dir(&self.dir) {
+            Ok(listing) => listing,
+            Err(err) => {
+                warn!("failed to list cache directory {}, error {}", self.dir.to_string_lossy(), err);
+                return vec![];
+            }
+        };
+        listing
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let meta = entry.metadata().ok()?;
+                if !meta.is_file() {
+                    return None;
+                }
+                Some((entry.path(), meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH)))
+            })
+            .collect()
+    }
+}
+
+impl Drop for KeyLock {
+    fn drop(&mut self) {
+        debug!("releasing lock at {}", self.pth.to_string_lossy());
+        remove_file(&self.pth);
+    }
+}
+
+/// Whether the lock file exists and the process that created it is still running.
+fn is_lock_held(pth: &Path) -> bool {
+    let Ok(content) = fs::read_to_string(pth) else {
+        return false;
+    };
+    match content.lines().next().unwrap_or("").trim().parse::<u32>() {
+        Ok(_) => running_lock_holder(&content).is_some(),
+        Err(_) => !is_old(pth, EMPTY_LOCK_ABANDONED_AFTER),
+    }
+}
+
+/// Whether the file exists and was not modified for longer than `age`.
+fn is_old(pth: &Path, age: Duration) -> bool {
+    pth.metadata()
+        .and_then(|meta| meta.modified())
+        .is_ok_and(|modified| modified.elapsed().unwrap_or(Duration::ZERO) > age)
+}
+
+/// Process id from the lock file content, if that process is still running.
+/// The start time of the process is compared too, if known, in case the pid was reused after a crash.
+fn running_lock_holder(content: &str) -> Option<u32> {
+    let mut lines = content.lines();
+    let pid = lines.next()?.trim().parse::<u32>().ok()?;
+    if !is_process_alive(pid) {
+        return None;
+    }
+    let expected_start = lines.next().map(|line| line.trim()).unwrap_or("");
+    if !expected_start.is_empty() && process_start_time(pid).is_some_and(|start| start != expected_start) {
+        debug!("process id {} of lock holder was reused by a different process", pid);
+        return None;
+    }
+    Some(pid)
+}
+
+/// Process id of the writer, if the path is a file from `shared_output_path`.
+fn shared_output_pid(pth: &Path) -> Option<u32> {
+    let name = pth.file_name()?.to_str()?.strip_suffix(".out")?;
+    let (_, pid) = name.rsplit_once(".run.")?;
+    pid.parse().ok()
+}
+
+/// Remove a file, ignoring it if another process already removed it. Returns whether this call removed it.
+fn remove_file(pth: &Path) -> bool {
+    match fs::remove_file(pth) {
+        Ok(()) => true,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                warn!("failed to remove cache file {}, error {}", pth.to_string_lossy(), err);
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::thread::sleep;
+
+    use super::*;
+
+    #[test]
+    fn corrupt_entry_is_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().to_owned(), 1024).unwrap();
+        store.write("good", &"hello".to_owned()).unwrap();
+        fs::write(store.entry_path("bad"), "{\"trunc").unwrap();
+        assert_eq!(store.read::<String>("good"), Some("hello".to_owned()));
+        assert_eq!(store.read::<String>("bad"), None);
+        assert!(!store.entry_path("bad").exists());
+        assert_eq!(store.entries().len(), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().to_owned(), 25).unwrap();
+        let value = "x".repeat(8);
+        store.write("first", &value).unwrap();
+        sleep(Duration::from_millis(20));
+        store.write("second", &value).unwrap();
+        sleep(Duration::from_millis(20));
+        assert!(store.read::<String>("first").is_some());
+        sleep(Duration::from_millis(20));
+        store.write("third", &value).unwrap();
+        let keys: Vec<String> = store.entries().into_iter().map(|entry| entry.key).collect();
+        assert_eq!(keys, vec!["first".to_owned(), "third".to_owned()]);
+        assert_eq!(store.clear(), 2);
+        assert_eq!(store.total_size(), 0);
+    }
+
+    #[test]
+    fn key_lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().to_owned(), 1024).unwrap();
+        let lock = store.try_lock("key", "refresh").unwrap().unwrap();
+        assert!(store.is_locked("key", "refresh"));
+        assert!(store.try_lock("key", "refresh").unwrap().is_none());
+        assert!(store.try_lock("other", "refresh").unwrap().is_some());
+        drop(lock);
+        assert!(!store.is_locked("key", "refresh"));
+        let _lock = store.try_lock("key", "run").unwrap().unwrap();
+        assert_eq!(store.lock_holder("key", "run"), Some(process::id()));
+        fs::write(store.lock_path("key", "refresh"), format!("{}", u32::MAX - 1)).unwrap();
+        assert!(store.try_lock("key", "refresh").unwrap().is_some());
+    }
+
+    #[test]
+    fn abandoned_lock_not_removed_while_guarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().to_owned(), 1024).unwrap();
+        fs::write(store.lock_path("key", "run"), format!("{}", u32::MAX - 1)).unwrap();
+        let guard_pth = dir.path().join("key.run.evict");
+        fs::write(&guard_pth, "").unwrap();
+        assert!(store.try_lock("key", "run").unwrap().is_none());
+        fs::remove_file(&guard_pth).unwrap();
+        assert!(store.try_lock("key", "run").unwrap().is_some());
+        assert!(!guard_pth.exists());
+    }
+
+    #[test]
+    fn lock_of_reused_pid_is_not_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().to_owned(), 1024).unwrap();
+        let pth = store.lock_path("key", "run");
+        fs::write(&pth, format!("{}\nnot-the-real-start-time", process::id())).unwrap();
+        assert_eq!(store.lock_holder("key", "run"), None);
+        assert!(!store.is_locked("key", "run"));
+        fs::write(&pth, format!("{}\n{}", process::id(), process_start_time(process::id()).unwrap())).unwrap();
+        assert_eq!(store.lock_holder("key", "run"), Some(process::id()));
+    }
+
+    #[test]
+    fn lock_fails_if_not_creatable() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().join("cache"), 1024).unwrap();
+        fs::remove_dir(store.dir()).unwrap();
+        assert!(store.try_lock("key", "run").is_err());
+    }
+
+    #[test]
+    fn output_of_stopped_run_is_abandoned() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CacheStore::in_dir(dir.path().to_owned(), 1024).unwrap();
+        fs::write(store.shared_output_path("key", process::id()), "").unwrap();
+        fs::write(store.shared_output_path("key", u32::MAX - 1), "").unwrap();
+        assert_eq!(store.remove_abandoned(), 1);
+        assert!(store.shared_output_path("key", process::id()).exists());
+    }
+}
@@ -1,9 +1,12 @@
+use ::std::time::Duration;
+
 use ::clap::Parser;
+use ::parse_duration0::parse as parse_dur;
 
 use crate::cmd::cmd_do::{mark_tasks_to_run, run_tasks, Status};
 use crate::cmd::cmd_type::{RunId, TaskStack, TaskType};
 use crate::cmd::create_cmd::create_tasks;
-use crate::common::{stdin_lines, CommandArgs, EmptyLineHandling};
+use crate::common::{stdin_lines, CommandArgs, EmptyLineHandling, PrefixTemplate, Timeout};
 use crate::ExitStatus;
 
 #[derive(Parser, Debug)]
@@ -49,6 +52,18 @@ pub struct BufArgs {
     #[arg(short = 'F', long)]
     /// Print a summary of failed commands at the end.
     pub failure_summary: bool,
+    #[arg(value_parser = PrefixTemplate::parse, long)]
+    /// Prefix each output line. Can use '%{date}', '%{time}', '%{nr}', '%{elapsed}', '%{stream}' (out or err) and '%{cmd}' placeholders.
+    pub prefix: Option<PrefixTemplate>,
+    #[arg(value_parser = parse_dur, short = 'T', long)]
+    /// Stop each command (and processes it started) if it takes longer than this, e.g. "30 min".
+    pub timeout: Option<Duration>,
+    #[arg(value_parser = parse_dur, long = "timeout-grace", default_value = "5s", requires = "timeout")]
+    /// After the timeout, how long to wait after SIGTERM before sending SIGKILL.
+    pub timeout_grace: Duration,
+    #[arg(long)]
+    /// Only show the commands that would run, and why, without running them. Can also set RUSHT_DRY_RUN.
+    pub dry_run: bool,
     #[command(subcommand)]
     pub cmd: CommandArgs,
     #[arg(long, hide_short_help = true, conflicts_with = "lines_with")]
@@ -59,6 +74,9 @@ pub struct BufArgs {
 #[test]
 fn test_cli_args() {
     BufArgs::try_parse_from(&["cmd", "-L", "%", "-c=5", "-F", "ls", "-Q", "%"]).unwrap();
+    BufArgs::try_parse_from(&["cmd", "-p=4", "--prefix", "[%{cmd}]", "ls"]).unwrap();
+    BufArgs::try_parse_from(&["cmd", "-T", "10s", "--timeout-grace", "1s", "ls"]).unwrap();
+    BufArgs::try_parse_from(&["cmd", "-u", "--dry-run", "ls"]).unwrap();
 }
 
 pub fn buf_cmd(args: BufArgs) -> ExitStatus {
@@ -100,6 +118,9 @@ pub fn buf_cmd(args: BufArgs) -> ExitStatus {
         args.continue_on_error || args.parallel > 1,
         args.parallel,
         args.quiet || args.mostly_quiet,
+        &args.prefix.unwrap_or_else(PrefixTemplate::none),
+        args.timeout.map(|limit| Timeout { limit, grace: args.timeout_grace }),
+        None,
     );
     if args.failure_summary {
         for (id, cmd) in &cmd_names {
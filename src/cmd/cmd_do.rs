@@ -1,11 +1,15 @@
+use ::std::path::PathBuf;
 use ::std::sync::atomic::AtomicUsize;
 use ::std::sync::atomic::Ordering;
 use ::std::sync::Arc;
+use ::std::time::Duration;
+use ::std::time::Instant;
 
 use ::clap::Parser;
 use ::dashmap::DashMap;
 use ::log::debug;
 use ::log::info;
+use ::parse_duration0::parse as parse_dur;
 use ::rand::Rng;
 use ::rayon::iter::{IntoParallelIterator, ParallelIterator};
 use ::rayon::ThreadPoolBuilder;
@@ -17,6 +21,12 @@ use crate::cmd::cmd_type::RunId;
 use crate::cmd::cmd_type::RunningTask;
 use crate::cmd::cmd_type::TaskStack;
 use crate::cmd::cmd_type::TaskType;
+use crate::common::explain;
+use crate::common::is_dry_run;
+use crate::common::skip_for_dry_run;
+use crate::common::PrefixTemplate;
+use crate::common::Timeout;
+use crate::common::TraceRecorder;
 use crate::ExitStatus;
 
 #[derive(Parser, Debug)]
@@ -64,12 +74,31 @@ pub struct DoArgs {
     #[arg(short = '0', long = "allow-empty")]
     /// Silently do nothing if there are no commands.
     pub allow_empty: bool,
+    #[arg(value_parser = PrefixTemplate::parse, long)]
+    /// Prefix each output line. Can use '%{date}', '%{time}', '%{nr}', '%{elapsed}', '%{stream}' (out or err) and '%{cmd}' placeholders.
+    pub prefix: Option<PrefixTemplate>,
+    #[arg(value_parser = parse_dur, short = 'T', long)]
+    /// Stop each command (and processes it started) if it takes longer than this, e.g. "30 min".
+    pub timeout: Option<Duration>,
+    #[arg(value_parser = parse_dur, long = "timeout-grace", default_value = "5s", requires = "timeout")]
+    /// After the timeout, how long to wait after SIGTERM before sending SIGKILL.
+    pub timeout_grace: Duration,
+    #[arg(long, hide_short_help = true)]
+    /// Write the timing of each command to this file, as a Chrome trace-event json (open in e.g. Perfetto).
+    pub trace: Option<PathBuf>,
+    #[arg(long)]
+    /// Only show the commands that would run, and why, without running them. Can also set RUSHT_DRY_RUN.
+    pub dry_run: bool,
 }
 
 #[test]
 fn test_cli_args() {
     DoArgs::try_parse_from(&["cmd", "-q", "-p=8", "--keep", "--all", "-F"]).unwrap();
     DoArgs::try_parse_from(&["cmd", "-P", "--all"]).unwrap();
+    DoArgs::try_parse_from(&["cmd", "-a", "--prefix", "%{cmd} %{stream}:"]).unwrap();
+    DoArgs::try_parse_from(&["cmd", "-a", "-T", "30 min"]).unwrap();
+    DoArgs::try_parse_from(&["cmd", "-a", "-p=4", "--trace", "/tmp/cmdo-trace.json"]).unwrap();
+    DoArgs::try_parse_from(&["cmd", "-c=2", "--dry-run"]).unwrap();
     assert!(DoArgs::try_parse_from(&["cmd", "-P", "-p=4"]).is_err());
 }
 
@@ -88,13 +117,24 @@ pub fn do_cmd(args: DoArgs) -> bool {
     }
 
     let to_run = mark_tasks_to_run(args.restart_running, args.all, args.count, &mut tasks, ts_s);
-    write(args.namespace.clone(), &tasks);
+    let dry_run = is_dry_run();
+    if !dry_run {
+        write(args.namespace.clone(), &tasks);
+    }
 
     let cmd_names: Vec<(RunId, String)> = to_run.iter()
         .map(|task| (task.run_id, task.as_str()))
         .collect();
+    let prefix = args.prefix.clone().unwrap_or_else(PrefixTemplate::none);
+    let timeout = args.timeout.map(|limit| Timeout { limit, grace: args.timeout_grace });
+    let trace = args.trace.as_ref().map(|_| TraceRecorder::new());
     let statuses = run_tasks(to_run, args.continue_on_error, args.parallel,
-        args.quiet || args.mostly_quiet);
+        args.quiet || args.mostly_quiet, &prefix, timeout, trace.as_ref());
+    if let (Some(trace), Some(trace_file)) = (&trace, &args.trace) {
+        if let Err(err) = trace.write_chrome_trace(trace_file) {
+            eprintln!("{}", err);
+        }
+    }
     if args.failure_summary {
         for (id, cmd) in &cmd_names {
             if matches!(statuses.get(id).map(|s| *s), Some(Status::Failed(_))) {
@@ -103,9 +143,16 @@ pub fn do_cmd(args: DoArgs) -> bool {
         }
     }
 
-    let tasks = read(args.namespace.clone());
-    let remaining = remove_completed_tasks(&args, tasks, &statuses);
-    write(args.namespace, &remaining);
+    let remaining = if dry_run {
+        let remaining = remove_completed_tasks(&args, tasks, &statuses);
+        explain(format_args!("not updating the stack, it would have {} command(s) left", remaining.len()));
+        read(args.namespace.clone())
+    } else {
+        let tasks = read(args.namespace.clone());
+        let remaining = remove_completed_tasks(&args, tasks, &statuses);
+        write(args.namespace, &remaining);
+        remaining
+    };
 
     if !args.quiet {
         if remaining.is_empty() {
@@ -125,6 +172,9 @@ pub fn run_tasks(
     continue_on_error: bool,
     parallel: u32,
     quiet: bool,
+    prefix: &PrefixTemplate,
+    timeout: Option<Timeout>,
+    trace: Option<&TraceRecorder>,
 ) -> Arc<DashMap<RunId, Status>> {
     let statuses = Arc::new(DashMap::new());
     to_run
@@ -151,6 +201,9 @@ pub fn run_tasks(
                             current_nr.fetch_add(1, Ordering::AcqRel),
                             total_count,
                             quiet,
+                            prefix,
+                            timeout,
+                            trace,
                         );
                         statuses.insert(id, status);
                     })
@@ -169,6 +222,9 @@ pub fn run_tasks(
                     current_nr.fetch_add(1, Ordering::AcqRel),
                     total_count,
                     quiet,
+                    prefix,
+                    timeout,
+                    trace,
                 );
                 statuses.insert(id, status);
                 status
@@ -203,7 +259,15 @@ fn verify_args(mut args: DoArgs) -> DoArgs {
     args
 }
 
-fn exec(task: RunningTask, current_nr: usize, total_count: usize, quiet: bool) -> (RunId, Status) {
+fn exec(
+    task: RunningTask,
+    current_nr: usize,
+    total_count: usize,
+    quiet: bool,
+    prefix: &PrefixTemplate,
+    timeout: Option<Timeout>,
+    trace: Option<&TraceRecorder>,
+) -> (RunId, Status) {
     if !quiet {
         if total_count > 1 {
             println!("run {}/{}: {}", current_nr, total_count, task.as_str());
@@ -212,7 +276,15 @@ fn exec(task: RunningTask, current_nr: usize, total_count: usize, quiet: bool) -
         }
     }
     let id = task.run_id;
-    let status = Status::from(task.task.execute_sync(!quiet));
+    let name = task.as_str();
+    let start = Instant::now();
+    let task = task.task.with_timeout(timeout);
+    let exit_status = skip_for_dry_run(&task, false)
+        .unwrap_or_else(|| task.execute_sync_with_prefix(!quiet, prefix));
+    if let Some(trace) = trace {
+        trace.record(name, start, Instant::now(), exit_status, vec![]);
+    }
+    let status = Status::from(exit_status);
     (id, status)
 }
 
@@ -8,7 +8,7 @@ use ::std::thread::spawn;
 use ::log::debug;
 use log::warn;
 
-use crate::common::{fail, CommandArgs, Task};
+use crate::common::{explain, fail, CommandArgs, Task};
 
 pub fn create_tasks(
     line_reader: impl FnOnce() -> Vec<String>,
@@ -41,7 +41,13 @@ pub fn create_tasks(
         let mut seen: HashSet<&String> = HashSet::new();
         line_reader()
             .iter()
-            .filter(|line| !unique || seen.insert(line))
+            .filter(|line| {
+                if !unique || seen.insert(line) {
+                    return true;
+                }
+                explain(format_args!("skipping duplicate input '{}'", line));
+                false
+            })
             .map(|input| {
                 task_from_template(&cmd, input, &templ, working_dir.as_ref(), stdin.as_ref())
             })
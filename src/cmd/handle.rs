@@ -1,4 +1,5 @@
 use crate::cmd::cmd_buf::{buf_cmd, BufArgs};
+use crate::common::enable_dry_run;
 use crate::common::stdin_lines;
 use crate::common::EmptyLineHandling;
 use crate::ExitStatus;
@@ -20,6 +21,9 @@ pub fn handle_add(mut args: AddArgs) -> ExitStatus {
 
 pub fn handle_do(args: DoArgs) -> ExitStatus {
     assert!(!args.parallel > 1, "parallel not implemented"); // TODO
+    if args.dry_run {
+        enable_dry_run();
+    }
 
     let all_ok = do_cmd(args);
     ExitStatus::of_is_ok(all_ok)
@@ -44,5 +48,8 @@ pub fn handle_list(args: ListArgs) -> ExitStatus {
 }
 
 pub fn handle_buf(args: BufArgs) -> ExitStatus {
+    if args.dry_run {
+        enable_dry_run();
+    }
     buf_cmd(args)
 }
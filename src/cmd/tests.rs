@@ -1,5 +1,6 @@
 use ::std::fs;
 use ::std::sync::Once;
+use ::std::time::Duration;
 
 use ::rand::Rng;
 use ::tempfile::NamedTempFile;
@@ -142,6 +143,7 @@ fn onebyone_add_run() {
         count: 1,
         all: true,
         parallel: 1,
+        parallel_per_core: false,
         restart_running: false,
         continue_on_error: false,
         drop_failed: false,
@@ -150,6 +152,11 @@ fn onebyone_add_run() {
         mostly_quiet: false,
         allow_empty: false,
         failure_summary: false,
+        prefix: None,
+        timeout: None,
+        timeout_grace: Duration::from_secs(5),
+        trace: None,
+        dry_run: false,
     });
     let out = list_cmds(ListArgs {
         namespace,
@@ -6,9 +6,17 @@ use crate::escape::NamesafeArgs;
 pub use self::cmd_args::CommandArgs;
 pub use self::dependent::Dependent;
 pub use self::dependent::run_all;
+pub use self::dry_run::enable_dry_run;
+pub use self::dry_run::explain;
+pub use self::dry_run::is_dry_run;
+pub use self::dry_run::skip_for_dry_run;
 pub use self::err::ExitStatus;
 pub use self::err::fail;
 pub use self::files::file_modified_time_in_seconds;
+pub use self::process::format_bytes;
+pub use self::process::is_process_alive;
+pub use self::process::process_start_time;
+pub use self::process::ResourceUsage;
 pub use self::re::get_first_match_or_all;
 pub use self::re::get_matches;
 pub use self::read::FileReader;
@@ -20,22 +28,34 @@ pub use self::read::VecReader;
 pub use self::stdin::EmptyLineHandling;
 pub use self::stdin::stdin_lines;
 pub use self::task::Task;
+pub use self::task::ProcessGroup;
+pub use self::task::Timeout;
 pub use self::time::current_time_user_str;
+pub use self::time::local_offset;
+pub use self::trace::TraceRecorder;
 pub use self::which::resolve_executable;
 pub use self::write::CollectorWriter;
 pub use self::write::DiscardWriter;
 pub use self::write::FirstItemWriter;
+pub use self::write::FunnelFactory;
+pub use self::write::InterleavedBuffer;
+pub use self::write::InterleavedWriter;
+pub use self::write::LineContainer;
+pub use self::write::LinePrefix;
 pub use self::write::LineWriter;
+pub use self::write::PrefixTemplate;
 pub use self::write::PrefixWriter;
 pub use self::write::RegexWatcherWriter;
 pub use self::write::StdWriter;
 pub use self::write::TeeWriter;
+pub use self::write::TemplatePrefixWriter;
 pub use self::write::VecWriter;
 
 mod time;
 mod async_gate;
 mod cmd_args;
 mod dependent;
+mod dry_run;
 mod err;
 mod exec;
 //mod exec2;  //TODO @mverleg: ENABLE
@@ -44,9 +64,11 @@ mod re;
 mod read;
 mod stdin;
 mod task;
+mod trace;
 mod which;
 mod write;
 mod files;
+mod process;
 
 pub fn safe_filename(text: &str) -> String {
     namesafe_line(
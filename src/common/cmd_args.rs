@@ -35,6 +35,19 @@ impl CommandArgs {
         }
         (CommandArgs::Cmd(first), CommandArgs::Cmd(second))
     }
+
+    /// Split at every occurrence of the separator. Parts may be empty.
+    pub fn split_all_at(self, separator: &str) -> Vec<CommandArgs> {
+        let mut parts = vec![vec![]];
+        for part in self.unpack() {
+            if part == separator {
+                parts.push(vec![])
+            } else {
+                parts.last_mut().unwrap().push(part)
+            }
+        }
+        parts.into_iter().map(CommandArgs::Cmd).collect()
+    }
 }
 
 #[cfg(test)]
@@ -58,4 +71,23 @@ mod tests {
             vec!["bbb".to_owned(), "--".to_owned(), "ccc".to_owned(),]
         );
     }
+
+    #[test]
+    fn split_all() {
+        let orig = CommandArgs::Cmd(vec![
+            "aaa".to_owned(),
+            "--".to_owned(),
+            "bbb".to_owned(),
+            "b2".to_owned(),
+            "--".to_owned(),
+            "--".to_owned(),
+        ]);
+        let parts = orig.split_all_at("--").into_iter().map(|part| part.unpack()).collect::<Vec<_>>();
+        assert_eq!(parts, vec![
+            vec!["aaa".to_owned()],
+            vec!["bbb".to_owned(), "b2".to_owned()],
+            vec![],
+            vec![],
+        ]);
+    }
 }
@@ -2,6 +2,7 @@ use ::std::rc::Rc;
 use ::std::sync::atomic::AtomicU64;
 use ::std::sync::atomic::Ordering;
 use ::std::time::Duration;
+use ::std::time::Instant;
 
 use ::futures::future::join_all;
 use ::log::debug;
@@ -10,7 +11,7 @@ use ::smallvec::SmallVec;
 
 use crate::common::async_gate::AsyncGate;
 use crate::common::write::FunnelFactory;
-use crate::common::{LineWriter, Task};
+use crate::common::{LineWriter, Task, TraceRecorder};
 use crate::ExitStatus;
 
 static DEBUG_NR: AtomicU64 = AtomicU64::new(0); //TODO @mverleg:
@@ -68,7 +69,7 @@ impl Dependent {
     }
 
     // Note this takes owned LineWriter instead of &mut because of run_all. Try using e.g. `FunnelWriter`.
-    pub async fn await_and_exec(&self, mut writer: impl LineWriter) -> ExitStatus {
+    pub async fn await_and_exec(&self, mut writer: impl LineWriter, trace: Option<&TraceRecorder>) -> ExitStatus {
         let count = self.dependencies.len();
         for (nr, dependency) in self.dependencies.iter().enumerate() {
             if dependency.gate.is_open() {
@@ -131,7 +132,13 @@ impl Dependent {
         }
         if let Some(task) = &self.task {
             self.current.open(false);
-            task.execute_with_stdout(true, &mut writer).await
+            let start = Instant::now();
+            let status = task.execute_with_stdout(true, &mut writer).await;
+            if let Some(trace) = trace {
+                let dependencies = self.dependencies.iter().map(|dep| dep.name.as_ref().clone()).collect();
+                trace.record(self.name.as_ref(), start, Instant::now(), status, dependencies);
+            }
+            status
         } else {
             self.current.open(true);
             ExitStatus::ok()
@@ -148,12 +155,13 @@ impl Dependent {
     }
 }
 
-pub async fn run_all(dependents: Vec<Dependent>, writer: &mut impl LineWriter) -> ExitStatus {
+/// Run all tasks concurrently, each after its dependencies. If `trace` is given, the timing of each task is recorded.
+pub async fn run_all(dependents: Vec<Dependent>, writer: &mut impl LineWriter, trace: Option<&TraceRecorder>) -> ExitStatus {
     let fac = FunnelFactory::new(writer);
     join_all(
         dependents
             .iter()
-            .map(|dep| dep.await_and_exec(fac.writer(dep.name.as_ref())))
+            .map(|dep| dep.await_and_exec(fac.writer(dep.name.as_ref()), trace))
             .collect::<Vec<_>>(),
     )
     .await
@@ -185,7 +193,7 @@ mod tests {
         let deps = vec![botm, mid1, top, mid2];
         match select(
             Box::pin(sleep(Duration::from_secs(3))),
-            Box::pin(run_all(deps, &mut StdWriter::stdout())),
+            Box::pin(run_all(deps, &mut StdWriter::stdout(), None)),
         )
         .await
         {
@@ -0,0 +1,89 @@
+use ::std::env;
+use ::std::fmt::Display;
+use ::std::sync::atomic::AtomicBool;
+use ::std::sync::atomic::Ordering;
+
+use ::itertools::Itertools;
+use ::log::debug;
+
+use crate::common::Task;
+use crate::ExitStatus;
+
+static DRY_RUN_ENV_NAME: &str = "RUSHT_DRY_RUN";
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Do not run the user's commands in this process, only show what would be run. Can also be enabled with `RUSHT_DRY_RUN`.
+pub fn enable_dry_run() {
+    debug!("enabling dry-run mode, tasks will be shown instead of run");
+    DRY_RUN.store(true, Ordering::Release)
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Acquire)
+        || env::var_os(DRY_RUN_ENV_NAME).is_some_and(|val| !val.is_empty())
+}
+
+/// Explain a decision that a tool made, e.g. skipping a task. Only shown in dry-run mode.
+pub fn explain(msg: impl Display) {
+    if is_dry_run() {
+        eprintln!("dry-run: {}", msg)
+    }
+}
+
+/// In dry-run mode, show the task and return the status to report for it, instead of running it.
+/// Only for the commands of the user; internal commands (like git queries for cache keys) should still run.
+pub fn skip_for_dry_run(task: &Task, streams_stdin: bool) -> Option<ExitStatus> {
+    if !is_dry_run() {
+        return None;
+    }
+    print_dry_run(task, streams_stdin);
+    Some(ExitStatus::ok())
+}
+
+fn print_dry_run(task: &Task, streams_stdin: bool) {
+    for line in dry_run_lines(task, streams_stdin) {
+        eprintln!("{}", line)
+    }
+}
+
+fn dry_run_lines(task: &Task, streams_stdin: bool) -> Vec<String> {
+    let mut lines = vec![format!("dry-run: would run {}", task.as_cmd_str())];
+    lines.push(format!("  cwd: {}", task.working_dir.to_string_lossy()));
+    if let Some(stdin) = &task.stdin {
+        lines.push(format!("  stdin: {}", stdin.replace('\n', "\\n")));
+    } else if streams_stdin {
+        lines.push("  stdin: (streamed from input)".to_owned());
+    }
+    for (key, value) in task.extra_envs.iter().sorted() {
+        lines.push(format!("  env: {}={}", key, value));
+    }
+    if let Some(timeout) = &task.timeout {
+        lines.push(format!("  timeout: {} ms", timeout.limit.as_millis()));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn describe_task() {
+        let task = Task::new_split(
+            vec!["grep".to_owned(), "-c".to_owned(), "a b".to_owned()],
+            PathBuf::from("/tmp"),
+            Some("one\ntwo".to_owned()),
+        ).with_extra_env(&[("B".to_owned(), "2".to_owned()), ("A".to_owned(), "1".to_owned())]);
+        let lines = dry_run_lines(&task, false);
+        assert!(lines[0].starts_with("dry-run: would run /"));
+        assert!(lines[0].ends_with("grep -c 'a b'"));
+        assert_eq!(lines[1..], vec![
+            "  cwd: /tmp".to_owned(),
+            "  stdin: one\\ntwo".to_owned(),
+            "  env: A=1".to_owned(),
+            "  env: B=2".to_owned(),
+        ]);
+    }
+}
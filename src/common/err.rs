@@ -58,6 +58,11 @@ impl ExitStatus {
         ExitStatus::of(1)
     }
 
+    /// The command was stopped because it exceeded its timeout (same code as coreutils `timeout`).
+    pub fn timeout() -> ExitStatus {
+        ExitStatus::of(124)
+    }
+
     pub fn code(&self) -> u8 {
         self.code
     }
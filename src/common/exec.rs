@@ -2,20 +2,33 @@
 
 use ::std::env;
 use ::std::iter;
+use ::std::process::Command as StdCommand;
+use ::std::sync::mpsc;
+use ::std::sync::mpsc::Receiver;
+use ::std::sync::mpsc::RecvTimeoutError;
 use ::std::thread;
 
 use ::async_std::io as aio;
+use ::async_std::prelude::FutureExt as AltExt;
+use ::async_std::process::Child;
 use ::async_std::process::Command;
 use ::async_std::process::Stdio;
 use ::async_std::task::block_on;
 use ::futures::AsyncBufReadExt;
+use ::futures::FutureExt;
 use ::itertools::Itertools;
 use ::log::debug;
 use futures::AsyncWriteExt;
 
+use crate::common::async_gate::AsyncGate;
+use crate::common::process::signal_process_group;
+use crate::common::process::ResourceUsage;
+#[cfg(unix)]
+use crate::common::process::wait_with_usage;
 use crate::common::write::FunnelFactory;
-use crate::common::{LineWriter, StdWriter, Task};
+use crate::common::{LineReader, LineWriter, PrefixTemplate, StdWriter, Task, Timeout, VecReader};
 use crate::observe::mon_task;
+use crate::observe::MonOptions;
 use crate::ExitStatus;
 
 static USE_SHELL_ENV_NAME: &'static str = "RUSHT_SHELL_EXEC";
@@ -26,6 +39,26 @@ impl Task {
         block_on(self.execute_with_stdout(monitor, writer))
     }
 
+    /// Like `execute_sync`, but prefix every line of output (not the monitoring lines).
+    pub fn execute_sync_with_prefix(&self, monitor: bool, prefix: &PrefixTemplate) -> ExitStatus {
+        let mut out_writer = StdWriter::stdout();
+        if monitor {
+            let funnel = FunnelFactory::new(&mut out_writer);
+            block_on(mon_task(
+                self,
+                &mut funnel.writer(""),
+                &mut funnel.writer(""),
+                &MonOptions { prefix: prefix.clone(), ..MonOptions::default() },
+            ))
+        } else {
+            let line_prefix = prefix.start(self.as_short_cmd_str());
+            block_on(self.execute_with_stdout_nomonitor(
+                &mut line_prefix.writer(&mut out_writer, "out"),
+                &mut line_prefix.writer(&mut StdWriter::stderr(), "err"),
+            ))
+        }
+    }
+
     pub async fn execute_with_stdout(
         &self,
         monitor: bool,
@@ -48,13 +81,7 @@ impl Task {
                 self,
                 &mut funnel.writer(""),
                 &mut funnel.writer(""),
-                true,
-                true,
-                true,
-                true,
-                false,
-                false,
-                &[],
+                &MonOptions::default(),
             )
             .await
         } else {
@@ -68,9 +95,40 @@ impl Task {
         out_writer: &mut impl LineWriter,
         err_writer: &mut impl LineWriter,
     ) -> ExitStatus {
-        let cmd = if env::var(USE_SHELL_ENV_NAME).is_ok() {
+        let cmd = self.build_cmd();
+        let result = self.execute_cmd_with_outerr(cmd, None::<&mut VecReader>, out_writer, err_writer, false).await;
+        status_or_report(result).0
+    }
+
+    /// Like `execute_with_stdout_nomonitor`, but also measures the resources used by the process (on unix).
+    pub async fn execute_with_usage_nomonitor(
+        &self,
+        out_writer: &mut impl LineWriter,
+        err_writer: &mut impl LineWriter,
+    ) -> (ExitStatus, Option<ResourceUsage>) {
+        let cmd = self.build_cmd();
+        let result = self.execute_cmd_with_outerr(cmd, None::<&mut VecReader>, out_writer, err_writer, true).await;
+        status_or_report(result)
+    }
+
+    /// Like `execute_with_stdout_nomonitor`, but streams stdin from a reader instead of `Task::stdin`.
+    /// Stops sending input if the process closes its stdin.
+    pub async fn execute_with_inouterr_nomonitor(
+        &self,
+        in_reader: &mut impl LineReader,
+        out_writer: &mut impl LineWriter,
+        err_writer: &mut impl LineWriter,
+    ) -> ExitStatus {
+        assert!(self.stdin.is_none(), "cannot use both stdin reader and fixed Task stdin");
+        let cmd = self.build_cmd();
+        let result = self.execute_cmd_with_outerr(cmd, Some(in_reader), out_writer, err_writer, false).await;
+        status_or_report(result).0
+    }
+
+    fn build_cmd(&self) -> Command {
+        let mut cmd = if env::var(USE_SHELL_ENV_NAME).is_ok() {
             debug!("using shell execution mode (because {USE_SHELL_ENV_NAME} is set); this is inexplicably much faster for mvn, but may cause escaping issues");
-            let mut cmd = Command::new("sh");
+            let mut cmd = StdCommand::new("sh");
             let joined_cmd = iter::once(format!("'{}'", self.cmd))
                 .chain(self.args.iter()
                     .inspect(|arg| if arg.contains('\'') {
@@ -82,29 +140,33 @@ impl Task {
             cmd
         } else {
             debug!("not using shell execution mode (because {USE_SHELL_ENV_NAME} is not set); this is the safe way but may be slower");
-            let mut cmd = Command::new(&self.cmd);
+            let mut cmd = StdCommand::new(&self.cmd);
             cmd.args(&self.args);
             cmd
         };
-        self.execute_cmd_with_outerr(cmd, out_writer, err_writer)
-            .await
-            .unwrap()
-        //TODO @mverleg: get rid of unwrap
+        if self.timeout.is_some() || self.process_group.is_some() {
+            // own process group, so that the whole tree can be stopped on timeout or by the caller
+            #[cfg(unix)]
+            ::std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        }
+        Command::from(cmd)
     }
 
     async fn execute_cmd_with_outerr(
         &self,
         mut base_cmd: Command,
+        in_reader: Option<&mut impl LineReader>,
         out_writer: &mut impl LineWriter,
         err_writer: &mut impl LineWriter,
-    ) -> Result<ExitStatus, String> {
+        measure_usage: bool,
+    ) -> Result<(ExitStatus, Option<ResourceUsage>), String> {
         // note: cannot log with async_std because it does not expose getters on Command
         // debug!("command to run: '{}' {}", base_cmd.get_program().to_string_lossy(),
         //     base_cmd.get_args().map(|a| format!("\"{}\"", a.to_string_lossy())).join(" "));
         let mut child = base_cmd
             .current_dir(&self.working_dir)
             .envs(&self.extra_envs)
-            .stdin(if self.stdin.is_some() {
+            .stdin(if self.stdin.is_some() || in_reader.is_some() {
                 Stdio::piped()
             } else {
                 Stdio::null()
@@ -121,12 +183,17 @@ impl Task {
             })?;
 
         // This uses threads because async_std spawn did not have scoped tasks, so writer needs to be 'static, which it is not
+        let exited = AsyncGate::new();
+        let exited_ref = &exited;
         thread::scope(move |scope| {
             let proc_out = child.stdout.take().unwrap();
             let proc_err = child.stderr.take().unwrap();
             let out_task = scope.spawn(move || forward_out(proc_out, out_writer));
             let err_task = scope.spawn(move || forward_out(proc_err, err_writer));
-            let in_task = if let Some(sin) = &self.stdin {
+            let in_task = if let Some(in_reader) = in_reader {
+                let proc_in = child.stdin.take().expect("child should have stdin piped");
+                Some(scope.spawn(move || forward_in(in_reader, proc_in, exited_ref)))
+            } else if let Some(sin) = &self.stdin {
                 let mut proc_in = child.stdin.take().expect("child should have stdin piped");
                 Some(scope.spawn(move || {
                     block_on(proc_in.write_all(sin.as_bytes())).expect("failed to send stdin")
@@ -134,8 +201,26 @@ impl Task {
             } else {
                 None
             };
+            let (done_sender, done_receiver) = mpsc::channel::<()>();
+            let pid = child.id();
+            if let Some(group) = &self.process_group {
+                group.started(pid);
+            }
+            let watchdog = self.timeout.map(|timeout| scope.spawn(move || stop_after_timeout(pid, timeout, done_receiver)));
             //TODO @mverleg: only do status() after stdin is closed, otherwise it closes it
-            let status = block_on(child.status()).map_err(|err| {
+            let status = if measure_usage {
+                wait_with_usage_or_status(&mut child)
+            } else {
+                block_on(child.status()).map(|status| (status.code(), None)).map_err(|err| err.to_string())
+            };
+            if let Some(group) = &self.process_group {
+                group.stopped();
+            }
+            drop(done_sender);
+            let timed_out = watchdog.is_some_and(|watchdog| watchdog.join().expect("thread panic"));
+            // the process will not read any more input, so do not wait for it
+            exited_ref.open(true);
+            let (code, usage) = status.map_err(|err| {
                 format!(
                     "failed to finish command '{}', error {}",
                     self.as_cmd_str(),
@@ -145,11 +230,66 @@ impl Task {
             out_task.join().expect("thread panic")?;
             err_task.join().expect("thread panic")?;
             in_task.map(|it| it.join().expect("thread panic"));
-            Ok(ExitStatus::of_code(status.code()))
+            let status = if timed_out { ExitStatus::timeout() } else { ExitStatus::of_code(code) };
+            Ok((status, usage))
         })
     }
 }
 
+/// Failing to start or finish the command is usually a user error (like a typo), so report it instead of panicking.
+fn status_or_report(result: Result<(ExitStatus, Option<ResourceUsage>), String>) -> (ExitStatus, Option<ResourceUsage>) {
+    result.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        (ExitStatus::err(), None)
+    })
+}
+
+/// Stop the process group if `done` is not signalled within the timeout. Returns whether it was stopped.
+fn stop_after_timeout(pid: u32, timeout: Timeout, done: Receiver<()>) -> bool {
+    if done.recv_timeout(timeout.limit) != Err(RecvTimeoutError::Timeout) {
+        return false;
+    }
+    eprintln!("command exceeded timeout of {} ms, terminating process group {}", timeout.limit.as_millis(), pid);
+    signal_process_group(pid, false);
+    if done.recv_timeout(timeout.grace) == Err(RecvTimeoutError::Timeout) {
+        eprintln!("command did not stop within {} ms, killing process group {}", timeout.grace.as_millis(), pid);
+        signal_process_group(pid, true);
+    }
+    true
+}
+
+#[cfg(unix)]
+fn wait_with_usage_or_status(child: &mut Child) -> Result<(Option<i32>, Option<ResourceUsage>), String> {
+    wait_with_usage(child.id()).map(|(code, usage)| (code, Some(usage)))
+}
+
+#[cfg(not(unix))]
+fn wait_with_usage_or_status(child: &mut Child) -> Result<(Option<i32>, Option<ResourceUsage>), String> {
+    debug!("resource usage is not available on this platform");
+    block_on(child.status()).map(|status| (status.code(), None)).map_err(|err| err.to_string())
+}
+
+fn forward_in(reader: &mut impl LineReader, mut stdin: impl aio::Write + Unpin, exited: &AsyncGate) {
+    loop {
+        let next = block_on(reader.read_line().map(Some).race(exited.wait().map(|_| None)));
+        let line = match next {
+            Some(Some(line)) => line,
+            Some(None) => break,
+            None => {
+                debug!("stopped sending input because process exited");
+                return;
+            }
+        };
+        let res = block_on(stdin.write_all(line.as_bytes()))
+            .and_then(|()| block_on(stdin.write_all(b"\n")));
+        if let Err(err) = res {
+            debug!("stopped sending input because process stdin was closed, err: {}", err);
+            return;
+        }
+    }
+    // stdin is closed when dropped, so the process knows the input ended
+}
+
 fn forward_out(stdout: impl aio::Read + Unpin, writer: &mut impl LineWriter) -> Result<(), String> {
     let mut out_buf = aio::BufReader::new(stdout);
     let mut line = String::new();
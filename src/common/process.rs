@@ -0,0 +1,249 @@
+use ::std::fmt;
+use ::std::fs;
+use ::std::io;
+use ::std::process::Command;
+use ::std::time::Duration;
+
+use ::log::debug;
+
+/// Whether a process with this pid exists (it may be owned by another user).
+#[cfg(unix)]
+pub fn is_process_alive(pid: u32) -> bool {
+    let Ok(c_pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // signal 0 does not send anything, but still checks that the process exists
+    let res = unsafe { libc::kill(c_pid, 0) };
+    let exists = res == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    exists && !is_zombie(pid)
+}
+
+#[cfg(unix)]
+/// Processes that exited but were not reaped by their parent yet, only detected on Linux.
+fn is_zombie(pid: u32) -> bool {
+    read_proc_stat_fields(pid)
+        .is_some_and(|fields| fields.first().is_some_and(|state| state == "Z"))
+}
+
+/// Fields from /proc/{pid}/stat after the executable name, so starting at field 3 (state).
+fn read_proc_stat_fields(pid: u32) -> Option<Vec<String>> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // the executable name is between parentheses and may contain spaces, so skip past it
+    let fields = &stat[stat.rfind(')')? + 1..];
+    Some(fields.split_whitespace().map(|field| field.to_owned()).collect())
+}
+
+#[cfg(not(unix))]
+pub fn is_process_alive(_pid: u32) -> bool {
+    debug!("cannot check if processes are alive on this platform, assuming they are");
+    true
+}
+
+/// Opaque identifier of when a process started, to detect that a pid was reused.
+/// The format differs per platform, so it is only useful for equality checks.
+pub fn process_start_time(pid: u32) -> Option<String> {
+    if let Some(mut fields) = read_proc_stat_fields(pid) {
+        // start time is field 22, which is the 20th after the name
+        return (fields.len() > 19).then(|| fields.swap_remove(19));
+    }
+    let out = Command::new("ps")
+        .args(["-o", "lstart=", "-p", &pid.to_string()])
+        .output();
+    match out {
+        Ok(out) if out.status.success() => {
+            let start = String::from_utf8_lossy(&out.stdout).trim().to_owned();
+            if start.is_empty() { None } else { Some(start) }
+        }
+        Ok(_) => None,
+        Err(err) => {
+            debug!("could not determine start time of process {pid} using ps, err {err}");
+            None
+        }
+    }
+}
+
+/// Send a signal to the process group led by `pid` (the command must have been started in its own group).
+#[cfg(unix)]
+pub fn signal_process_group(pid: u32, force: bool) {
+    let Ok(c_pid) = libc::pid_t::try_from(pid) else {
+        return;
+    };
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    if unsafe { libc::kill(-c_pid, signal) } != 0 {
+        debug!("could not signal process group {}, err {}", pid, io::Error::last_os_error());
+    }
+}
+
+#[cfg(not(unix))]
+pub fn signal_process_group(pid: u32, _force: bool) {
+    debug!("cannot stop process group {} on this platform", pid);
+}
+
+/// Resources used by a finished process, including the descendants it waited for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceUsage {
+    pub peak_rss_bytes: u64,
+    pub user_cpu: Duration,
+    pub sys_cpu: Duration,
+    /// Bytes read from and written to storage, only available on Linux.
+    pub io_bytes: Option<(u64, u64)>,
+}
+
+impl fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "peak mem {}, cpu {:.1}s user + {:.1}s sys",
+            format_bytes(self.peak_rss_bytes), self.user_cpu.as_secs_f64(), self.sys_cpu.as_secs_f64())?;
+        if let Some((read, written)) = self.io_bytes {
+            write!(f, ", read {}, wrote {}", format_bytes(read), format_bytes(written))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Wait for a child process to exit and reap it, returning the exit code (None if killed by a signal) and resource usage.
+/// The child must not be waited for in any other way.
+#[cfg(unix)]
+pub fn wait_with_usage(pid: u32) -> Result<(Option<i32>, ResourceUsage), String> {
+    let c_pid = libc::pid_t::try_from(pid).map_err(|_| format!("invalid pid {}", pid))?;
+    // wait for exit without reaping, so that /proc still has the io counters
+    let io_bytes = if cfg!(target_os = "linux") {
+        wait_for_exit_without_reaping(c_pid)?;
+        read_proc_io(pid)
+    } else {
+        None
+    };
+    let mut status: libc::c_int = 0;
+    let mut rusage: libc::rusage = unsafe { ::std::mem::zeroed() };
+    loop {
+        let res = unsafe { libc::wait4(c_pid, &mut status, 0, &mut rusage) };
+        if res == c_pid {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(format!("failed to wait for process {}, error {}", pid, err));
+        }
+    }
+    let code = libc::WIFEXITED(status).then(|| libc::WEXITSTATUS(status));
+    // ru_maxrss is in kilobytes on Linux but in bytes on macOS
+    let rss_unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    let usage = ResourceUsage {
+        peak_rss_bytes: (rusage.ru_maxrss.max(0) as u64) * rss_unit,
+        user_cpu: timeval_duration(rusage.ru_utime),
+        sys_cpu: timeval_duration(rusage.ru_stime),
+        io_bytes,
+    };
+    debug!("process {} exited with code {:?}, used {}", pid, code, usage);
+    Ok((code, usage))
+}
+
+#[cfg(target_os = "linux")]
+fn wait_for_exit_without_reaping(c_pid: libc::pid_t) -> Result<(), String> {
+    loop {
+        let mut info: libc::siginfo_t = unsafe { ::std::mem::zeroed() };
+        let res = unsafe { libc::waitid(libc::P_PID, c_pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT) };
+        if res == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(format!("failed to wait for process {}, error {}", c_pid, err));
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn wait_for_exit_without_reaping(_c_pid: libc::pid_t) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn timeval_duration(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec.max(0) as u64) + Duration::from_micros(time.tv_usec.max(0) as u64)
+}
+
+/// Storage bytes read and written according to /proc/{pid}/io, which includes reaped descendants.
+fn read_proc_io(pid: u32) -> Option<(u64, u64)> {
+    let content = fs::read_to_string(format!("/proc/{pid}/io")).ok()?;
+    let field = |name: &str| content.lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    Some((field("read_bytes:")?, field("write_bytes:")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::process;
+
+    use super::*;
+
+    #[test]
+    fn current_process_alive() {
+        assert!(is_process_alive(process::id()));
+    }
+
+    #[test]
+    fn finished_process_dead() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!is_process_alive(pid));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn usage_of_finished_child() {
+        let mut child = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        let result = wait_with_usage(child.id());
+        // normally already reaped by `wait_with_usage`, this is for when it failed
+        if result.is_err() {
+            let _ = child.kill();
+        }
+        let _ = child.wait();
+        let (code, usage) = result.unwrap();
+        assert_eq!(code, Some(3));
+        assert!(usage.peak_rss_bytes > 0);
+        assert_eq!(usage.io_bytes.is_some(), cfg!(target_os = "linux"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminate_group() {
+        use ::std::os::unix::process::CommandExt;
+        let mut child = Command::new("sleep").arg("10").process_group(0).spawn().unwrap();
+        signal_process_group(child.id(), false);
+        assert!(!child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn usage_display() {
+        let usage = ResourceUsage {
+            peak_rss_bytes: 3 * 1024 * 1024 / 2,
+            user_cpu: Duration::from_millis(2500),
+            sys_cpu: Duration::from_millis(100),
+            io_bytes: Some((100, 2048)),
+        };
+        assert_eq!(usage.to_string(), "peak mem 1.5 MB, cpu 2.5s user + 0.1s sys, read 100 B, wrote 2.0 KB");
+    }
+
+    #[test]
+    fn start_time_stable() {
+        let start = process_start_time(process::id());
+        assert!(start.is_some());
+        assert_eq!(start, process_start_time(process::id()));
+    }
+}
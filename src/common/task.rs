@@ -2,6 +2,10 @@ use ::std::collections::HashMap;
 use ::std::env;
 use ::std::fmt::Write;
 use ::std::path::PathBuf;
+use ::std::sync::atomic::AtomicU32;
+use ::std::sync::atomic::Ordering;
+use ::std::sync::Arc;
+use ::std::time::Duration;
 
 use ::itertools::Itertools;
 use ::lazy_static::lazy_static;
@@ -10,6 +14,7 @@ use ::serde::Deserialize;
 use ::serde::Serialize;
 use dirs::home_dir;
 
+use crate::common::process::signal_process_group;
 use crate::common::resolve_executable;
 
 lazy_static! {
@@ -25,8 +30,56 @@ pub struct Task {
     pub stdin: Option<String>,
     #[serde(default)]
     pub extra_envs: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Timeout>,
+    #[serde(skip)]
+    pub process_group: Option<ProcessGroup>,
 }
 
+/// Stop the command (and its process group) after `limit`, forcefully if it did not stop within `grace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timeout {
+    pub limit: Duration,
+    pub grace: Duration,
+}
+
+/// Runs the command in its own process group, and tracks it while running, so that the whole tree can be stopped.
+/// Clones share the tracked process, so a clone can be kept to stop the task from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessGroup {
+    pid: Arc<AtomicU32>,
+}
+
+impl ProcessGroup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn started(&self, pid: u32) {
+        self.pid.store(pid, Ordering::Release)
+    }
+
+    pub(crate) fn stopped(&self) {
+        self.pid.store(0, Ordering::Release)
+    }
+
+    /// Send SIGTERM (or SIGKILL if `force`) to the process group, if the command is running.
+    pub fn signal(&self, force: bool) {
+        let pid = self.pid.load(Ordering::Acquire);
+        if pid != 0 {
+            signal_process_group(pid, force)
+        }
+    }
+}
+
+impl PartialEq for ProcessGroup {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.pid, &other.pid)
+    }
+}
+
+impl Eq for ProcessGroup {}
+
 impl Task {
     pub fn new(
         cmd: String,
@@ -55,6 +108,8 @@ impl Task {
             working_dir,
             stdin,
             extra_envs,
+            timeout: None,
+            process_group: None,
         }
     }
 
@@ -84,7 +139,7 @@ impl Task {
 
     pub fn with_extra_env(self, key_values: &[(String, String)]) -> Task {
         debug_assert!(!key_values.is_empty());
-        let Task { cmd, args, working_dir, stdin, mut extra_envs } = self.clone();
+        let Task { cmd, args, working_dir, stdin, mut extra_envs, timeout, process_group } = self.clone();
         for (key, value) in key_values {
             extra_envs.insert(key.into(), value.into());
         }
@@ -94,9 +149,19 @@ impl Task {
             working_dir,
             stdin,
             extra_envs,
+            timeout,
+            process_group,
         }
     }
 
+    pub fn with_timeout(self, timeout: Option<Timeout>) -> Task {
+        Task { timeout, ..self }
+    }
+
+    pub fn with_process_group(self, process_group: ProcessGroup) -> Task {
+        Task { process_group: Some(process_group), ..self }
+    }
+
     pub fn as_cmd_str(&self) -> String {
         let mut txt = String::new();
         if let Some(home) = home_dir() {
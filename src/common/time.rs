@@ -1,6 +1,12 @@
+use ::std::sync::OnceLock;
+
+use ::log::debug;
 use ::time::OffsetDateTime;
+use ::time::UtcOffset;
 use ::time::macros::format_description;
 
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
 pub fn current_time_user_str() -> String {
     // the `time` crate always fails on Mac and Linux when inferring timezone
     // because  of a vulnerability in libc when getting timezones
@@ -10,3 +16,12 @@ pub fn current_time_user_str() -> String {
         Err(_) => format!("{} (utc)", OffsetDateTime::now_utc().format(format).unwrap())
     }
 }
+
+/// The local timezone offset, determined on the first call. The `time` crate can only determine it
+/// while the process has a single thread, so call this at startup; if it fails, this is UTC.
+pub fn local_offset() -> UtcOffset {
+    *LOCAL_OFFSET.get_or_init(|| UtcOffset::current_local_offset().unwrap_or_else(|err| {
+        debug!("could not determine local timezone, using utc, err {}", err);
+        UtcOffset::UTC
+    }))
+}
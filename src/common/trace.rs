@@ -0,0 +1,132 @@
+use ::std::fs;
+use ::std::path::Path;
+use ::std::sync::Arc;
+use ::std::sync::Mutex;
+use ::std::time::Instant;
+
+use ::log::debug;
+use ::serde::Serialize;
+use ::serde_json::json;
+
+use crate::ExitStatus;
+
+/// Records when tasks ran, to export as a Chrome trace-event file (viewable in e.g. Perfetto or chrome://tracing).
+#[derive(Debug, Clone)]
+pub struct TraceRecorder {
+    origin: Instant,
+    spans: Arc<Mutex<Vec<TraceSpan>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TraceSpan {
+    name: String,
+    start_us: u64,
+    end_us: u64,
+    exit_code: u8,
+    dependencies: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    ts: u64,
+    dur: u64,
+    pid: u32,
+    tid: usize,
+    args: serde_json::Value,
+}
+
+impl TraceRecorder {
+    pub fn new() -> Self {
+        TraceRecorder {
+            origin: Instant::now(),
+            spans: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    pub fn record(&self, name: impl Into<String>, start: Instant, end: Instant, status: ExitStatus, dependencies: Vec<String>) {
+        let span = TraceSpan {
+            name: name.into(),
+            start_us: start.saturating_duration_since(self.origin).as_micros() as u64,
+            end_us: end.saturating_duration_since(self.origin).as_micros() as u64,
+            exit_code: status.code(),
+            dependencies,
+        };
+        self.spans.lock().expect("trace lock poisoned").push(span)
+    }
+
+    /// Json in Chrome trace-event format. Tasks are placed on as few rows as possible without overlapping.
+    pub fn to_chrome_trace(&self) -> String {
+        let mut spans = self.spans.lock().expect("trace lock poisoned").clone();
+        spans.sort_by_key(|span| (span.start_us, span.end_us));
+        let mut lane_ends: Vec<u64> = vec![];
+        let mut events = vec![];
+        for span in spans {
+            let lane = match lane_ends.iter().position(|end| *end <= span.start_us) {
+                Some(lane) => lane,
+                None => {
+                    lane_ends.push(0);
+                    lane_ends.len() - 1
+                }
+            };
+            lane_ends[lane] = span.end_us;
+            events.push(TraceEvent {
+                name: span.name,
+                cat: if span.exit_code == 0 { "ok" } else { "failed" },
+                ph: "X",
+                ts: span.start_us,
+                dur: span.end_us.saturating_sub(span.start_us),
+                pid: 1,
+                tid: lane + 1,
+                args: json!({
+                    "exit_code": span.exit_code,
+                    "dependencies": span.dependencies,
+                }),
+            });
+        }
+        json!({ "traceEvents": events, "displayTimeUnit": "ms" }).to_string()
+    }
+
+    pub fn write_chrome_trace(&self, pth: &Path) -> Result<(), String> {
+        fs::write(pth, self.to_chrome_trace())
+            .map_err(|err| format!("failed to write trace to '{}', error {}", pth.to_string_lossy(), err))?;
+        debug!("wrote trace to '{}'", pth.to_string_lossy());
+        Ok(())
+    }
+}
+
+impl Default for TraceRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn overlapping_tasks_on_separate_rows() {
+        let trace = TraceRecorder::new();
+        let t0 = trace.origin;
+        let ms = Duration::from_millis;
+        trace.record("compile", t0, t0 + ms(10), ExitStatus::ok(), vec![]);
+        trace.record("test-a", t0 + ms(10), t0 + ms(30), ExitStatus::ok(), vec!["compile".to_owned()]);
+        trace.record("test-b", t0 + ms(12), t0 + ms(20), ExitStatus::err(), vec!["compile".to_owned()]);
+        let json: serde_json::Value = serde_json::from_str(&trace.to_chrome_trace()).unwrap();
+        let events = json["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["tid"], 1);
+        assert_eq!(events[1]["name"], "test-a");
+        assert_eq!(events[1]["tid"], 1);
+        assert_eq!(events[1]["ts"], 10_000);
+        assert_eq!(events[1]["dur"], 20_000);
+        assert_eq!(events[2]["tid"], 2);
+        assert_eq!(events[2]["cat"], "failed");
+        assert_eq!(events[2]["args"]["dependencies"][0], "compile");
+    }
+}
@@ -1,8 +1,12 @@
+use ::std::collections::VecDeque;
 use ::std::fmt;
 // using async caused deadlocks in concurrent mvn commands
 use ::std::fmt::Debug;
 use ::std::io;
 use ::std::io::Write;
+use ::std::sync::atomic::AtomicU64;
+use ::std::sync::atomic::Ordering;
+use ::std::time::Instant;
 use std::process::exit;
 
 use ::async_std::sync::Arc;
@@ -13,8 +17,13 @@ use ::log::debug;
 use ::log::warn;
 use ::regex::Regex;
 use ::smallvec::SmallVec;
+use ::time::macros::format_description;
+use ::time::OffsetDateTime;
+use ::time::UtcOffset;
 use futures::future::join;
 
+use crate::common::local_offset;
+
 #[async_trait]
 pub trait LineWriter: Debug + Send {
     async fn write_line(&mut self, line: impl AsRef<str> + Send);
@@ -221,6 +230,65 @@ impl<'a, W1: LineWriter, W2: LineWriter> LineWriter for TeeWriter<'a, W1, W2> {
     }
 }
 
+/// Collects lines from several streams, keeping the order in which they were written.
+#[derive(Debug, Clone)]
+pub struct InterleavedBuffer {
+    max: usize,
+    lines: Arc<Mutex<VecDeque<(&'static str, String)>>>,
+}
+
+impl InterleavedBuffer {
+    pub fn new() -> Self {
+        Self::tail(usize::MAX)
+    }
+
+    /// Keep only the last `max` lines.
+    pub fn tail(max: usize) -> Self {
+        InterleavedBuffer {
+            max,
+            lines: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn writer(&self, stream: &'static str) -> InterleavedWriter {
+        InterleavedWriter {
+            stream,
+            buffer: self.clone(),
+        }
+    }
+
+    /// All lines with the name of their stream, in the order they were written.
+    pub async fn take(&self) -> Vec<(&'static str, String)> {
+        self.lines.lock().await.drain(..).collect()
+    }
+}
+
+impl Default for InterleavedBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct InterleavedWriter {
+    stream: &'static str,
+    buffer: InterleavedBuffer,
+}
+
+#[async_trait]
+impl LineWriter for InterleavedWriter {
+    async fn write_line(&mut self, line: impl AsRef<str> + Send) {
+        if self.buffer.max == 0 {
+            return;
+        }
+        let mut lines = self.buffer.lines.lock().await;
+        if lines.len() >= self.buffer.max {
+            lines.pop_front();
+        }
+        lines.push_back((self.stream, line.as_ref().to_owned()))
+    }
+}
+
 /// Several handles can send to the same writer asynchronously.
 #[derive(Debug)]
 pub struct FunnelWriter<'a, W: LineWriter> {
@@ -320,6 +388,136 @@ impl<'a, W: LineWriter> LineWriter for PrefixWriter<'a, W> {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PrefixPart {
+    Text(String),
+    Date,
+    Time,
+    Nr,
+    Elapsed,
+    Stream,
+    Cmd,
+}
+
+/// Line prefix with placeholders '%{date}', '%{time}', '%{nr}', '%{elapsed}', '%{stream}' and '%{cmd}',
+/// which are expanded for every line. Date and time are local, or UTC if the timezone could not be determined.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrefixTemplate {
+    parts: Vec<PrefixPart>,
+}
+
+impl PrefixTemplate {
+    pub fn none() -> Self {
+        PrefixTemplate::default()
+    }
+
+    pub fn parse(template: &str) -> Result<Self, String> {
+        // parsing happens at startup, while the local timezone can still be determined
+        local_offset();
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("%{") {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            let part = match &rest[start + 2..start + len] {
+                "date" => PrefixPart::Date,
+                "time" => PrefixPart::Time,
+                "nr" => PrefixPart::Nr,
+                "elapsed" => PrefixPart::Elapsed,
+                "stream" => PrefixPart::Stream,
+                "cmd" => PrefixPart::Cmd,
+                unknown => return Err(format!("unknown placeholder '%{{{}}}' in prefix, \
+                    expected one of %{{date}}, %{{time}}, %{{nr}}, %{{elapsed}}, %{{stream}} or %{{cmd}}", unknown)),
+            };
+            text.push_str(&rest[..start]);
+            if !text.is_empty() {
+                parts.push(PrefixPart::Text(text.split_off(0)));
+            }
+            parts.push(part);
+            rest = &rest[start + len + 1..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(PrefixPart::Text(text));
+        }
+        Ok(PrefixTemplate { parts })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// Start expanding the template for a command, with line numbers and elapsed time counting from now.
+    pub fn start(&self, cmd: impl Into<String>) -> LinePrefix {
+        LinePrefix {
+            parts: Arc::new(self.parts.clone()),
+            cmd: Arc::new(cmd.into()),
+            start: Instant::now(),
+            offset: local_offset(),
+            line_nr: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+/// Expands a `PrefixTemplate` for one command. Clones share the line counter, so that stdout and stderr are numbered together.
+#[derive(Debug, Clone)]
+pub struct LinePrefix {
+    parts: Arc<Vec<PrefixPart>>,
+    cmd: Arc<String>,
+    start: Instant,
+    offset: UtcOffset,
+    line_nr: Arc<AtomicU64>,
+}
+
+impl LinePrefix {
+    pub fn expand(&self, stream: &str) -> String {
+        let nr = self.line_nr.fetch_add(1, Ordering::AcqRel) + 1;
+        let now = OffsetDateTime::now_utc().to_offset(self.offset);
+        let mut prefix = String::new();
+        for part in self.parts.iter() {
+            match part {
+                PrefixPart::Text(text) => prefix.push_str(text),
+                PrefixPart::Date => prefix.push_str(&now.format(format_description!("[year]-[month]-[day]")).unwrap()),
+                PrefixPart::Time => prefix.push_str(&now.format(format_description!("[hour]:[minute]:[second]")).unwrap()),
+                PrefixPart::Nr => prefix.push_str(&nr.to_string()),
+                PrefixPart::Elapsed => prefix.push_str(&format!("{:.3}s", self.start.elapsed().as_secs_f64())),
+                PrefixPart::Stream => prefix.push_str(stream),
+                PrefixPart::Cmd => prefix.push_str(&self.cmd),
+            }
+        }
+        prefix
+    }
+
+    pub fn writer<'a, W: LineWriter>(&self, delegate: &'a mut W, stream: &'static str) -> TemplatePrefixWriter<'a, W> {
+        TemplatePrefixWriter {
+            delegate,
+            prefix: self.clone(),
+            stream,
+        }
+    }
+}
+
+/// Prefix each line with an expanded template, followed by a space. Lines are unchanged if the template is empty.
+#[derive(Debug)]
+pub struct TemplatePrefixWriter<'a, W: LineWriter> {
+    delegate: &'a mut W,
+    prefix: LinePrefix,
+    stream: &'static str,
+}
+
+#[async_trait]
+impl<'a, W: LineWriter> LineWriter for TemplatePrefixWriter<'a, W> {
+    async fn write_line(&mut self, line: impl AsRef<str> + Send) {
+        if self.prefix.parts.is_empty() {
+            return self.delegate.write_line(line).await
+        }
+        let prefixed_line = format!("{} {}", self.prefix.expand(self.stream), line.as_ref());
+        self.delegate.write_line(&prefixed_line).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -328,4 +526,41 @@ mod tests {
     fn can_craete_writer_without_type_annotation() {
         let _ = StdWriter::stdout();
     }
+
+    #[async_std::test]
+    async fn interleaved_keeps_order() {
+        let buffer = InterleavedBuffer::new();
+        let mut out = buffer.writer("out");
+        let mut err = buffer.writer("err");
+        out.write_line("a").await;
+        err.write_line("b").await;
+        out.write_line("c").await;
+        assert_eq!(buffer.take().await, vec![("out", "a".to_owned()), ("err", "b".to_owned()), ("out", "c".to_owned())]);
+        let tail = InterleavedBuffer::tail(1);
+        tail.writer("out").write_line("a").await;
+        tail.writer("err").write_line("b").await;
+        assert_eq!(tail.take().await, vec![("err", "b".to_owned())]);
+    }
+
+    #[test]
+    fn prefix_template_parse() {
+        assert!(PrefixTemplate::parse("").unwrap().is_empty());
+        assert!(PrefixTemplate::parse("[%{unknown}]").is_err());
+        let prefix = PrefixTemplate::parse("[%{nr}/%{stream}] %{cmd} %{").unwrap().start("ls -a");
+        assert_eq!(prefix.expand("out"), "[1/out] ls -a %{");
+        assert_eq!(prefix.clone().expand("err"), "[2/err] ls -a %{");
+    }
+
+    #[async_std::test]
+    async fn template_prefix_writer() {
+        let prefix = PrefixTemplate::parse("%{date}T%{time}+%{elapsed}").unwrap().start("");
+        let mut out = VecWriter::new();
+        prefix.writer(&mut out, "out").write_line("hello").await;
+        let line = out.get().pop().unwrap();
+        let re = Regex::new(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}\+\d+\.\d{3}s hello$").unwrap();
+        assert!(re.is_match(&line), "{}", line);
+        let mut out = VecWriter::new();
+        PrefixTemplate::none().start("").writer(&mut out, "out").write_line("as-is").await;
+        assert_eq!(out.get(), vec!["as-is"]);
+    }
 }
@@ -1,10 +1,13 @@
 pub use self::between::between;
 pub use self::between_args::BetweenArgs;
+pub use self::expect_lines::expect_lines;
+pub use self::expect_lines_args::ExpectLinesArgs;
 pub use self::filter_args::FilterArgs;
 pub use self::filtering::filter;
 pub use self::grab::grab;
 pub use self::grab_args::GrabArgs;
 pub use self::handle::handle_between;
+pub use self::handle::handle_expect_lines;
 pub use self::handle::handle_filter;
 pub use self::handle::handle_grab;
 pub use self::handle::handle_unique;
@@ -16,6 +19,8 @@ pub use self::unique::UniqueArgs;
 
 mod between;
 mod between_args;
+mod expect_lines;
+mod expect_lines_args;
 mod filter_args;
 mod filtering;
 mod grab;
@@ -0,0 +1,177 @@
+use ::std::fmt::Write;
+
+use ::log::debug;
+
+use crate::common::LineReader;
+use crate::common::LineWriter;
+use crate::filter::ExpectLinesArgs;
+
+/// Check that the number of lines is in the expected range, and if so, write them to the writer
+/// (unless quiet). Input is buffered until the count is known, or until the minimum is reached
+/// if there is no maximum, after which lines are passed through directly. Returns the number of lines.
+pub async fn expect_lines(
+    args: ExpectLinesArgs,
+    reader: &mut impl LineReader,
+    writer: &mut impl LineWriter,
+) -> Result<u32, String> {
+    let max = args.count.max();
+    // without a maximum, the count cannot become wrong once the minimum is reached
+    let is_open_ended = max == u32::MAX;
+    let mut is_streaming = is_open_ended && args.count.min() == 0;
+    let mut lines = vec![];
+    let mut count: u32 = 0;
+    while let Some(line) = reader.read_line().await {
+        count = count.saturating_add(1);
+        if is_streaming {
+            if !args.quiet {
+                writer.write_line(line).await;
+            }
+            continue;
+        }
+        if count <= max || lines.len() < args.preview {
+            lines.push(line.to_owned());
+        } else if lines.len() > args.preview {
+            debug!("expect_lines found more than {} lines, no longer buffering", max);
+            lines.truncate(args.preview);
+        }
+        if is_open_ended && count >= args.count.min() {
+            debug!("expect_lines found {} lines, which is enough, passing the rest through", count);
+            is_streaming = true;
+            if !args.quiet {
+                for line in lines.drain(..) {
+                    writer.write_line(line).await;
+                }
+            }
+        }
+    }
+    if is_streaming {
+        return Ok(count);
+    }
+    if !args.count.includes(count) {
+        return Err(wrong_count_msg(&args, count, &lines));
+    }
+    debug!("expect_lines found {} lines, which is in range {}", count, args.count);
+    if !args.quiet {
+        for line in lines {
+            writer.write_line(line).await;
+        }
+    }
+    Ok(count)
+}
+
+fn wrong_count_msg(args: &ExpectLinesArgs, count: u32, lines: &[String]) -> String {
+    let mut msg = format!("expected {} lines, but found {}", args.count, count);
+    let shown = lines.len().min(args.preview);
+    if shown == 0 {
+        return msg;
+    }
+    write!(msg, "; the first {} {}:", shown, if shown == 1 { "is" } else { "are" }).unwrap();
+    for line in &lines[..shown] {
+        write!(msg, "\n  {}", line).unwrap();
+    }
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::str::FromStr;
+
+    use ::async_trait::async_trait;
+
+    use crate::common::CollectorWriter;
+    use crate::common::LineContainer;
+    use crate::common::VecReader;
+    use crate::common::VecWriter;
+    use crate::find::IntRange;
+
+    use super::*;
+
+    async fn check(range: &str, quiet: bool, input: Vec<&str>) -> (Result<u32, String>, Vec<String>) {
+        let args = ExpectLinesArgs {
+            count: IntRange::from_str(range).unwrap(),
+            quiet,
+            preview: 2,
+        };
+        let mut writer = VecWriter::new();
+        let res = expect_lines(args, &mut VecReader::new(input), &mut writer).await;
+        (res, writer.get())
+    }
+
+    #[async_std::test]
+    async fn passes_through_in_range() {
+        let (res, out) = check("2,3", false, vec!["a", "b", "c"]).await;
+        assert_eq!(res, Ok(3));
+        assert_eq!(out, vec!["a", "b", "c"]);
+    }
+
+    #[async_std::test]
+    async fn quiet_swallows_output() {
+        let (res, out) = check("1", true, vec!["a"]).await;
+        assert_eq!(res, Ok(1));
+        assert!(out.is_empty());
+    }
+
+    #[async_std::test]
+    async fn open_ended_streams_after_minimum() {
+        let (res, out) = check("2,", false, vec!["a", "b", "c"]).await;
+        assert_eq!(res, Ok(3));
+        assert_eq!(out, vec!["a", "b", "c"]);
+        let (res, out) = check("0,", false, vec!["a"]).await;
+        assert_eq!(res, Ok(1));
+        assert_eq!(out, vec!["a"]);
+    }
+
+    /// Records how many lines were already written when the input ends.
+    #[derive(Debug)]
+    struct EndCheckReader {
+        lines: Vec<String>,
+        current: String,
+        written: LineContainer,
+        written_at_end: usize,
+    }
+
+    #[async_trait]
+    impl LineReader for EndCheckReader {
+        async fn read_line(&mut self) -> Option<&str> {
+            if self.lines.is_empty() {
+                self.written_at_end = self.written.snapshot().await.len();
+                return None;
+            }
+            self.current = self.lines.remove(0);
+            Some(&self.current)
+        }
+    }
+
+    #[async_std::test]
+    async fn open_ended_streams_before_end_of_input() {
+        let args = ExpectLinesArgs {
+            count: IntRange::from_str("2,").unwrap(),
+            quiet: false,
+            preview: 2,
+        };
+        let mut writer = CollectorWriter::new();
+        let mut reader = EndCheckReader {
+            lines: vec!["a".to_owned(), "b".to_owned(), "c".to_owned()],
+            current: String::new(),
+            written: writer.lines(),
+            written_at_end: 0,
+        };
+        let res = expect_lines(args, &mut reader, &mut writer).await;
+        assert_eq!(res, Ok(3));
+        assert_eq!(reader.written_at_end, 3);
+    }
+
+    #[async_std::test]
+    async fn too_few_lines() {
+        let (res, out) = check("1,", false, vec![]).await;
+        assert_eq!(res, Err("expected [1,] lines, but found 0".to_owned()));
+        assert!(out.is_empty());
+    }
+
+    #[async_std::test]
+    async fn too_many_lines_preview() {
+        let (res, out) = check(",1", false, vec!["a", "b", "c", "d"]).await;
+        assert_eq!(res, Err("expected [0,1] lines, but found 4; the first 2 are:\n  a\n  b".to_owned()));
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+use ::clap::Parser;
+
+use crate::find::IntRange;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "expect_lines",
+    about = "Fail unless the input has the expected number of lines, passing it through unchanged otherwise."
+)]
+pub struct ExpectLinesArgs {
+    #[arg(default_value = "1,")]
+    /// Expected number of lines, e.g. '1' (exactly), '2,10' (inclusive), ',1' (upto) or '1,' (at least). Default is at least one.
+    pub count: IntRange,
+    #[arg(short = 'q', long)]
+    /// Do not pass the input through, only check the number of lines.
+    pub quiet: bool,
+    #[arg(short = 'p', long, default_value = "5")]
+    /// Number of lines to show in the error if the count is wrong.
+    pub preview: usize,
+}
+
+#[test]
+fn test_cli_args() {
+    ExpectLinesArgs::try_parse_from(&["cmd", "-q", ",1"]).unwrap();
+    ExpectLinesArgs::try_parse_from(&["cmd", "--preview", "2", "3"]).unwrap();
+}
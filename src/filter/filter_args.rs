@@ -16,6 +16,9 @@ pub struct FilterArgs {
     #[arg(short = 'i', long)]
     /// Invert the command result, keeping all lines for which the command fails instead
     pub invert: bool,
+    #[arg(long)]
+    /// Only show the commands that would run, and why, without running them. Can also set RUSHT_DRY_RUN.
+    pub dry_run: bool,
     #[command(subcommand)]
     pub cmd: CommandArgs,
 }
@@ -23,4 +26,5 @@ pub struct FilterArgs {
 #[test]
 fn test_cli_args() {
     FilterArgs::try_parse_from(&["cmd", "--", "test", "-f"]).unwrap();
+    FilterArgs::try_parse_from(&["cmd", "-i", "--dry-run", "test", "-f"]).unwrap();
 }
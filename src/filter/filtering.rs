@@ -1,6 +1,6 @@
 use ::log::debug;
 
-use crate::common::{get_first_match_or_all, LineReader, LineWriter};
+use crate::common::{explain, get_first_match_or_all, skip_for_dry_run, LineReader, LineWriter};
 use crate::filter::FilterArgs;
 
 //TODO @mverleg: pattern {} in cmd!
@@ -12,7 +12,8 @@ pub async fn filter(args: FilterArgs, reader: &mut impl LineReader, writer: &mut
         let arg = get_first_match_or_all(&args.by, line);
         let mut task = base_task.clone();
         task.push_arg(arg);
-        let status = task.execute_sync(false);
+        let status = skip_for_dry_run(&task, false)
+            .unwrap_or_else(|| task.execute_sync(false));
         if expect_success == status.is_ok() {
             debug!(
                 "keep line {} after task {} (code: {})",
@@ -20,6 +21,7 @@ pub async fn filter(args: FilterArgs, reader: &mut impl LineReader, writer: &mut
                 task.as_cmd_str(),
                 status.code()
             );
+            explain(format_args!("keeping line '{}' (code {})", line, status.code()));
             writer.write_line(line).await;
         } else {
             debug!(
@@ -28,6 +30,7 @@ pub async fn filter(args: FilterArgs, reader: &mut impl LineReader, writer: &mut
                 task.as_cmd_str(),
                 status.code()
             );
+            explain(format_args!("discarding line '{}' (code {})", line, status.code()));
         }
     }
 }
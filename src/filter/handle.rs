@@ -1,10 +1,12 @@
 use ::log::debug;
 
-use crate::common::{DiscardWriter, StdWriter, StdinReader, VecReader, FileReader};
+use crate::common::{enable_dry_run, DiscardWriter, StdWriter, StdinReader, VecReader, FileReader};
 use crate::filter::between;
+use crate::filter::expect_lines;
 use crate::filter::filter;
 use crate::filter::unique;
 use crate::filter::BetweenArgs;
+use crate::filter::ExpectLinesArgs;
 use crate::filter::FilterArgs;
 use crate::filter::UniqueArgs;
 use crate::ExitStatus;
@@ -121,6 +123,9 @@ pub async fn handle_unique(args: UniqueArgs) -> ExitStatus {
 }
 
 pub async fn handle_filter(args: FilterArgs) -> ExitStatus {
+    if args.dry_run {
+        enable_dry_run();
+    }
     filter(args, &mut StdinReader::new(), &mut StdWriter::stdout()).await;
     ExitStatus::ok()
 }
@@ -135,6 +140,16 @@ pub async fn handle_between(args: BetweenArgs) -> ExitStatus {
     }
 }
 
+pub async fn handle_expect_lines(args: ExpectLinesArgs) -> ExitStatus {
+    match expect_lines(args, &mut StdinReader::new(), &mut StdWriter::stdout()).await {
+        Ok(_) => ExitStatus::ok(),
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitStatus::err()
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,13 @@
+use ::clap::Parser;
+use ::rusht::filter::handle_expect_lines;
+use ::rusht::filter::ExpectLinesArgs;
+use ::rusht::ExitStatus;
+
+#[async_std::main]
+async fn main() -> ExitStatus {
+    env_logger::init_from_env(
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn"),
+    );
+    let args = ExpectLinesArgs::parse();
+    handle_expect_lines(args).await
+}
@@ -1,5 +1,6 @@
 pub use self::dir_with::find_dir_with;
 pub use self::dir_with_args::DirWithArgs;
+pub use self::dir_with_args::IntRange;
 pub use self::dir_with_args::Nested;
 pub use self::dir_with_args::OnErr;
 pub use self::dir_with_args::Order;
@@ -115,6 +115,14 @@ impl IntRange {
     pub fn is_provided(&self) -> bool {
         self.provided
     }
+
+    pub fn min(&self) -> u32 {
+        self.min
+    }
+
+    pub fn max(&self) -> u32 {
+        self.max
+    }
 }
 
 impl Default for IntRange {
@@ -184,6 +192,8 @@ impl fmt::Display for IntRange {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         if self.min == self.max {
             write!(f, "{}", self.min)
+        } else if self.max == u32::MAX {
+            write!(f, "[{},]", self.min)
         } else {
             write!(f, "[{},{}]", self.min, self.max)
         }
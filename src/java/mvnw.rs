@@ -16,6 +16,7 @@ use crate::common::LineWriter;
 use crate::common::RegexWatcherWriter;
 use crate::common::run_all;
 use crate::common::TeeWriter;
+use crate::common::TraceRecorder;
 use crate::ExitStatus;
 use crate::java::MvnCmdConfig;
 use crate::java::mvnw_args::AffectedPolicy;
@@ -93,6 +94,7 @@ async fn mvnw_dir(
     }
 
     let show_cmds_only = args.show_cmds_only;
+    let trace_file = args.trace.clone();
     let rebuild_if_match = args
         .rebuild_if_match
         .iter()
@@ -116,7 +118,8 @@ async fn mvnw_dir(
         has_pattern.store(true, Ordering::Release)
     });
     let mut tee_writer = TeeWriter::new(writer, &mut watcher);
-    let mut status = run_all(cmds, &mut tee_writer).await;
+    let trace = trace_file.as_ref().map(|_| TraceRecorder::new());
+    let mut status = run_all(cmds, &mut tee_writer, trace.as_ref()).await;
     if has_pattern.load(Ordering::Acquire) {
         eprintln!("going to clean rebuild because a --rebuild-if-match was matched");
         let mut clean_config = cmd_config;
@@ -128,7 +131,12 @@ async fn mvnw_dir(
         //TODO @mverleg: this seems to always happen on selective clean, i.e. with profile when clean runs as a separate mvn command - not just for re-run
         //TODO @mverleg: it also happens when both checkstyle and -v (version) are enabled, which also triggers two simultaneous mvn commands - just version (-vL) does not
         //dbg!(&io::stdout()); //TODO @mverleg: TEMPORARY! REMOVE THIS!
-        status = run_all(clean_config.build_cmds(), writer).await;
+        status = run_all(clean_config.build_cmds(), writer, trace.as_ref()).await;
+    }
+    if let (Some(trace), Some(trace_file)) = (&trace, &trace_file) {
+        if let Err(err) = trace.write_chrome_trace(trace_file) {
+            eprintln!("{}", err);
+        }
     }
     if status.is_ok() {
         Ok(())
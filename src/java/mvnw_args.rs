@@ -113,6 +113,9 @@ pub struct MvnwArgs {
     /// Maven profiles to activate. Prefix '!' to deactivate.
     #[arg(short = 'P', long = "profile")]
     pub profiles: Vec<Profile>,
+    /// Write the timing of each maven command to this file, as a Chrome trace-event json (open in e.g. Perfetto).
+    #[arg(long, hide_short_help = true)]
+    pub trace: Option<PathBuf>,
     /// Maven projects to build. Defaults to current working directory.
     #[arg(long = "proj-root", hide_short_help = true)]
     pub proj_roots: Vec<PathBuf>,
@@ -132,6 +135,7 @@ fn test_cli_args() {
     MvnwArgs::try_parse_from(&[
         "cmd",
         "--test-none",
+        "--trace=/tmp/mvnw-trace.json",
         "--max-exec-memory=2048",
         "--fail-if-added=//TODO @mverleg:",
         "--fail-if-added=^import .*\\.shaded\\.",
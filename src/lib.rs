@@ -12,6 +12,7 @@ pub mod find;
 pub mod java;
 pub mod observe;
 pub mod rsh;
+pub mod shtest;
 pub mod textproc;
 pub mod wait;
 mod shywolf;
@@ -9,6 +9,7 @@ use ::rusht::cmd::{AddArgs, DoArgs, DropArgs, ListArgs};
 use ::rusht::escape::handle_namesafe;
 use ::rusht::escape::NamesafeArgs;
 use rusht::filter::{handle_between, BetweenArgs};
+use ::rusht::filter::{handle_expect_lines, ExpectLinesArgs};
 use ::rusht::filter::{handle_filter, FilterArgs};
 use ::rusht::filter::{handle_grab, handle_unique};
 use ::rusht::filter::{GrabArgs, UniqueArgs};
@@ -21,12 +22,15 @@ use rusht::find::FilesWithArgs;
 use ::rusht::java::{handle_mvnw, MvnwArgs};
 use rusht::java::{handle_pomp, PompArgs};
 use ::rusht::observe::{handle_mon, MonArgs};
+use ::rusht::observe::{handle_mon_history, MonHistoryArgs};
 use ::rusht::observe::{handle_piped, PipedArgs};
 use ::rusht::rsh::{handle_rsh, RshArgs};
+use ::rusht::shtest::{handle_shtest, ShtestArgs};
 use ::rusht::textproc::batched_args::BatchedArgs;
 use ::rusht::textproc::handle::handle_batched;
 use ::rusht::wait::handle_locked;
 use ::rusht::wait::LockedArgs;
+use ::rusht::wait::{handle_wait_for, WaitForArgs};
 use ::rusht::ExitStatus;
 
 #[derive(Parser, Debug)]
@@ -55,15 +59,22 @@ enum SubCmd {
     Unique(UniqueArgs),
     Filter(FilterArgs),
     Locked(LockedArgs),
+    #[clap(name = "wait_for")]
+    WaitFor(WaitForArgs),
     Namesafe(NamesafeArgs),
     Mvnw(MvnwArgs),
     Pomp(PompArgs),
     Mon(MonArgs),
+    #[clap(name = "mon_history")]
+    MonHistory(MonHistoryArgs),
     Piped(PipedArgs),
     Batched(BatchedArgs),
     Jl(JlArgs),
     Between(BetweenArgs),
+    #[clap(name = "expect_lines")]
+    ExpectLines(ExpectLinesArgs),
     Rsh(RshArgs),
+    Shtest(ShtestArgs),
 }
 
 #[test]
@@ -90,15 +101,19 @@ async fn main() -> ExitStatus {
         SubCmd::Unique(sub_args) => handle_unique(sub_args).await,
         SubCmd::Filter(sub_args) => handle_filter(sub_args).await,
         SubCmd::Locked(sub_args) => handle_locked(sub_args),
+        SubCmd::WaitFor(sub_args) => handle_wait_for(sub_args),
         SubCmd::Namesafe(sub_args) => handle_namesafe(sub_args).await,
         SubCmd::Mvnw(sub_args) => handle_mvnw(sub_args).await,
         SubCmd::Pomp(sub_args) => handle_pomp(sub_args),
         SubCmd::Mon(sub_args) => handle_mon(sub_args).await,
+        SubCmd::MonHistory(sub_args) => handle_mon_history(sub_args),
         SubCmd::Piped(sub_args) => handle_piped(sub_args).await,
         SubCmd::Batched(sub_args) => handle_batched(sub_args).await,
         SubCmd::Jl(sub_args) => handle_jl(sub_args).await,
         SubCmd::Rsh(sub_args) => handle_rsh(sub_args),
+        SubCmd::Shtest(sub_args) => handle_shtest(sub_args),
         SubCmd::Between(sub_args) => handle_between(sub_args).await,
+        SubCmd::ExpectLines(sub_args) => handle_expect_lines(sub_args).await,
     }
 }
 
@@ -1,9 +1,16 @@
 pub use self::chain::chained;
 pub use self::handle_mon::handle_mon;
+pub use self::handle_mon::handle_mon_history;
 pub use self::handle_piped::handle_piped;
 pub use self::mon::mon;
 pub use self::mon::mon_task;
 pub use self::mon_args::MonArgs;
+pub use self::mon_history_args::MonHistoryArgs;
+pub use self::mon_history_query::mon_history;
+pub use self::mon_hooks::HookOn;
+pub use self::mon_hooks::MonHooks;
+pub use self::mon_options::MonOptions;
+pub use self::mon_retry::MonRetry;
 pub use self::piped::piped;
 pub use self::piped_args::PipedArgs;
 pub use self::sound::sound_notification;
@@ -13,6 +20,14 @@ mod handle_mon;
 mod handle_piped;
 mod mon;
 mod mon_args;
+mod mon_history;
+mod mon_history_args;
+mod mon_history_query;
+mod mon_hooks;
+mod mon_options;
+mod mon_retry;
+mod mon_watch;
 mod piped;
 mod piped_args;
 mod sound;
+mod sound_backend;
@@ -9,7 +9,7 @@ use ::log::debug;
 
 use ::async_trait::async_trait;
 
-use crate::common::{LineReader, LineWriter};
+use crate::common::{LineReader, LineWriter, ProcessGroup};
 
 //TODO @mverleg: maybe could be more efficient using single-line buffer and custom wakers, like AsyncGate
 
@@ -22,14 +22,39 @@ enum PipeItem {
 #[derive(Debug)]
 pub struct ChainWriter {
     sender: Sender<PipeItem>,
+    is_closed: bool,
+    writing_process: Option<ProcessGroup>,
+}
+
+impl ChainWriter {
+    /// Stop the process writing to this chain when the reader is closed, like SIGPIPE would.
+    pub fn stop_on_close(mut self, writing_process: ProcessGroup) -> Self {
+        self.writing_process = Some(writing_process);
+        self
+    }
+
+    /// Whether the reading side went away, so that lines are no longer sent.
+    pub fn is_closed(&self) -> bool {
+        self.is_closed
+    }
 }
 
 #[async_trait]
 impl LineWriter for ChainWriter {
     async fn write_line(&mut self, line: impl AsRef<str> + Send) {
+        if self.is_closed {
+            return;
+        }
         let line = line.as_ref().to_owned();
         debug!("chain write: {}", &line); //TODO @mark: TEMPORARY! REMOVE THIS!
-        self.sender.send(PipeItem::Line(line)).await.unwrap()
+        if self.sender.send(PipeItem::Line(line)).await.is_err() {
+            // like a broken pipe, the reading side is no longer interested
+            debug!("chain reader was closed, stopping the writer");
+            self.is_closed = true;
+            if let Some(process) = &self.writing_process {
+                process.signal(false);
+            }
+        }
     }
 }
 
@@ -37,9 +62,8 @@ impl Drop for ChainWriter {
     fn drop(&mut self) {
         // TODO rewrite for async drop if supported
         debug!("ending chain writer pipe"); //TODO @mark: TEMPORARY! REMOVE THIS!
-        match block_on(self.sender.send(PipeItem::End)) {
-            Ok(_) => {}
-            Err(err) => eprintln!("error in Drop of ChainWriter: {:?}", err),
+        if block_on(self.sender.send(PipeItem::End)).is_err() {
+            debug!("chain reader was closed before the writer");
         }
     }
 }
@@ -73,7 +97,7 @@ pub fn chained(buffer_size: usize) -> (ChainWriter, ChainReader) {
     let buffer_size = max(2, buffer_size);
     let (sender, receiver) = bounded(buffer_size);
     (
-        ChainWriter { sender },
+        ChainWriter { sender, is_closed: false, writing_process: None },
         ChainReader {
             receiver,
             current: PipeItem::Line("".to_string()),
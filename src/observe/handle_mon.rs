@@ -7,11 +7,15 @@ use crate::common::StdWriter;
 use crate::ExitStatus;
 use crate::observe::mon::mon;
 use crate::observe::mon_args::MonArgs;
+use crate::observe::mon_history;
+use crate::observe::MonHistoryArgs;
 
 static MON_FULL_CMD_VAR_NAME: &'static str = "MON_FULL_CMD";
+static MON_HISTORY_VAR_NAME: &str = "MON_HISTORY";
 
 pub async fn handle_mon(mut args: MonArgs) -> ExitStatus {
     update_full_cmd_flag_from_env(&mut args);
+    update_history_flag_from_env(&mut args);
     if args.use_stdout {
         debug!("use `mon` with monitor lines logged to stdout");
         mon(args, &mut StdWriter::stdout(), &mut StdWriter::stdout()).await
@@ -21,15 +25,39 @@ pub async fn handle_mon(mut args: MonArgs) -> ExitStatus {
     }
 }
 
-fn update_full_cmd_flag_from_env(args: &mut MonArgs) {
-    if !args.full_command {
-        match env::var(MON_FULL_CMD_VAR_NAME) {
-            Ok(val) => if !val.trim().is_empty() && val != "0" {
-                debug!("showing full `mon` command because env {MON_FULL_CMD_VAR_NAME} is set");
-                args.full_command = true;
+pub fn handle_mon_history(args: MonHistoryArgs) -> ExitStatus {
+    match mon_history(args) {
+        Ok(lines) => {
+            for line in lines {
+                println!("{}", line);
             }
-            Err(VarError::NotPresent) => {}
-            Err(_) => panic!("cannot read var {MON_FULL_CMD_VAR_NAME}"),
+            ExitStatus::ok()
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitStatus::err()
         }
     }
 }
+
+fn update_full_cmd_flag_from_env(args: &mut MonArgs) {
+    if !args.full_command && is_env_flag_set(MON_FULL_CMD_VAR_NAME) {
+        debug!("showing full `mon` command because env {MON_FULL_CMD_VAR_NAME} is set");
+        args.full_command = true;
+    }
+}
+
+fn update_history_flag_from_env(args: &mut MonArgs) {
+    if !args.history && is_env_flag_set(MON_HISTORY_VAR_NAME) {
+        debug!("recording `mon` history because env {MON_HISTORY_VAR_NAME} is set");
+        args.history = true;
+    }
+}
+
+fn is_env_flag_set(name: &str) -> bool {
+    match env::var(name) {
+        Ok(val) => !val.trim().is_empty() && val != "0",
+        Err(VarError::NotPresent) => false,
+        Err(_) => panic!("cannot read var {name}"),
+    }
+}
@@ -1,9 +1,34 @@
+use ::std::io::stdin;
+use ::std::io::IsTerminal;
+use ::std::thread;
+
+use ::async_std::task::block_on;
+use ::log::debug;
+
+use crate::common::LineReader;
+use crate::common::LineWriter;
 use crate::common::StdWriter;
 use crate::common::StdinReader;
+use crate::common::VecReader;
+use crate::observe::chained;
 use crate::observe::piped::piped;
 use crate::observe::piped_args::PipedArgs;
 use crate::ExitStatus;
 
 pub async fn handle_piped(args: PipedArgs) -> ExitStatus {
-    piped(args, &mut StdinReader::new(), &mut StdWriter::stdout()).await
+    if stdin().is_terminal() {
+        // do not wait for interactive input if the first command does not read stdin
+        debug!("stdin is a terminal, not passing it to the first command");
+        piped(args, &mut VecReader::new(Vec::<String>::new()), &mut StdWriter::stdout()).await
+    } else {
+        // reading stdin blocks, so do it in a separate thread that is abandoned if the first command finishes
+        let (mut stdin_writer, mut stdin_reader) = chained(16);
+        thread::spawn(move || {
+            let mut reader = StdinReader::new();
+            while let Some(line) = block_on(reader.read_line()) {
+                block_on(stdin_writer.write_line(line));
+            }
+        });
+        piped(args, &mut stdin_reader, &mut StdWriter::stdout()).await
+    }
 }
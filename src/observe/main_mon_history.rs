@@ -0,0 +1,13 @@
+use ::clap::Parser;
+
+use ::rusht::observe::handle_mon_history;
+use ::rusht::observe::MonHistoryArgs;
+use ::rusht::ExitStatus;
+
+fn main() -> ExitStatus {
+    env_logger::init_from_env(
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn"),
+    );
+    let args = MonHistoryArgs::parse();
+    handle_mon_history(args)
+}
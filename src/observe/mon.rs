@@ -1,15 +1,34 @@
+use ::itertools::Itertools;
 use ::log::debug;
+use ::time::OffsetDateTime;
 use std::collections::HashSet;
 use std::hash::Hash;
+use ::std::fs;
+use ::std::path::Path;
+use ::std::sync::atomic::AtomicBool;
+use ::std::sync::atomic::Ordering;
 use ::std::time::Instant;
+
+use ::async_std::task::sleep;
 use std::fmt::Display;
 use crate::common::current_time_user_str;
+use crate::common::FunnelFactory;
+use crate::common::InterleavedBuffer;
 use crate::common::LineWriter;
-use crate::common::PrefixWriter;
+use crate::common::RegexWatcherWriter;
+use crate::common::ResourceUsage;
 use crate::common::StdWriter;
 use crate::common::Task;
-use crate::common::VecWriter;
+use crate::common::Timeout;
+use crate::common::TeeWriter;
 use crate::observe::mon_args::MonArgs;
+use crate::observe::mon_history::append_history;
+use crate::observe::mon_history::history_path;
+use crate::observe::mon_history::HistoryEntry;
+use crate::observe::mon_history::HISTORY_TAIL_LINES;
+use crate::observe::mon_hooks::HookPayload;
+use crate::observe::mon_options::MonOptions;
+use crate::observe::mon_watch::mon_watch;
 use crate::observe::sound_notification;
 use crate::ExitStatus;
 
@@ -18,60 +37,37 @@ pub async fn mon(
     output_writer: &mut impl LineWriter,
     monitor_writer: &mut impl LineWriter,
 ) -> ExitStatus {
-    let task = args.cmd.clone().into_task();
-    if let Some(mut prefix) = args.prefix.clone() {
-        assert!(
-            !prefix.contains("%{date}"),
-            "placeholders not supported yet for mon --prefix"
-        );
-        assert!(
-            !prefix.contains("%{time}"),
-            "placeholders not supported yet for mon --prefix"
-        );
-        prefix.push(' ');
-        mon_task_with_writer(
-            &task,
-            args,
-            &mut PrefixWriter::new(output_writer, prefix),
-            monitor_writer,
-        ).await
-    } else {
-        mon_task_with_writer(&task, args, output_writer, monitor_writer).await
+    let timeout = args.timeout.map(|limit| Timeout { limit, grace: args.timeout_grace });
+    let task = args.cmd.clone().into_task().with_timeout(timeout);
+    let opts = MonOptions::from(&args);
+    if !args.watch.is_empty() {
+        return mon_watch(&task, &opts, &args, output_writer, monitor_writer);
     }
-}
-
-pub async fn mon_task_with_writer(
-    task: &Task,
-    args: MonArgs,
-    output_writer: &mut impl LineWriter,
-    monitor_writer: &mut impl LineWriter,
-) -> ExitStatus {
-    mon_task(
-        task,
-        output_writer,
-        monitor_writer,
-        !args.no_print_cmd,
-        args.full_command,
-        !args.no_output_on_success,
-        !args.no_timing,
-        args.sound_success,
-        args.sound_failure,
-        &args.print_envs,
-    ).await
+    mon_task(&task, output_writer, monitor_writer, &opts).await
 }
 
 pub async fn mon_task(
     mut task: &Task,
     output_writer: &mut impl LineWriter,
     monitor_writer: &mut impl LineWriter,
-    print_cmd: bool,
-    full_cmd: bool,
-    output_on_success: bool,
-    timing: bool,
-    sound_success: bool,
-    sound_failure: bool,
-    print_envs: &[String],
+    opts: &MonOptions,
 ) -> ExitStatus {
+    let MonOptions {
+        print_cmd,
+        full_cmd,
+        output_on_success,
+        timing,
+        sound_success,
+        sound_failure,
+        ref print_envs,
+        record_history,
+        ref hooks,
+        ref prefix,
+        ref log_file,
+        report_usage,
+        ref retry,
+    } = *opts;
+    let log_file = log_file.as_deref();
     let print_envs = unique_envs(print_envs);
     //TODO @mverleg: print_envs
     let cmd_str = if full_cmd {
@@ -99,54 +95,124 @@ pub async fn mon_task(
         task = owned_task.as_ref().unwrap();
         // ^ not beautiful, but making task owned or mutable was too impactful; this is good enough
     };
+    let start = OffsetDateTime::now_utc();
     let t0 = Instant::now();
-    let status = if output_on_success {
-        let mut err_writer = StdWriter::stderr();
-        task.execute_with_stdout_nomonitor(output_writer, &mut err_writer)
-            .await
-    } else {
-        debug!("mon buffering output, will show on error");
-        let mut out_buffer = VecWriter::new();
-        let mut err_writer = StdWriter::stderr();
-        let status = task
-            .execute_with_stdout_nomonitor(&mut out_buffer, &mut err_writer)
-            .await;
-        if status.is_err() {
-            eprintln!("printing all output because process failed");
-            output_writer.write_all_lines(out_buffer.get().iter()).await;
+    let keep_all = !output_on_success || log_file.is_some();
+    let keep_tail = record_history || !hooks.is_empty();
+    let mut err_writer = StdWriter::stderr();
+    let mut attempt = 0;
+    let (status, usage, output) = loop {
+        attempt += 1;
+        let attempt_t0 = Instant::now();
+        let line_prefix = prefix.start(cmd_str.clone());
+        let buffer = if keep_all {
+            InterleavedBuffer::new()
+        } else {
+            InterleavedBuffer::tail(if keep_tail { HISTORY_TAIL_LINES } else { 0 })
+        };
+        let output_matched = AtomicBool::new(false);
+        let mut watcher = RegexWatcherWriter::new(retry.patterns(), |_| {
+            output_matched.store(true, Ordering::Release)
+        });
+        let watcher_funnel = FunnelFactory::new(&mut watcher);
+        let (status, usage) = if output_on_success {
+            execute(
+                task,
+                &mut line_prefix.writer(&mut TeeWriter::new(output_writer, &mut TeeWriter::new(
+                    &mut buffer.writer("out"), &mut watcher_funnel.writer(""))), "out"),
+                &mut line_prefix.writer(&mut TeeWriter::new(&mut err_writer, &mut TeeWriter::new(
+                    &mut buffer.writer("err"), &mut watcher_funnel.writer(""))), "err"),
+                report_usage,
+            ).await
+        } else {
+            debug!("mon buffering stdout and stderr, will show on error");
+            execute(
+                task,
+                &mut line_prefix.writer(&mut TeeWriter::new(&mut buffer.writer("out"), &mut watcher_funnel.writer("")), "out"),
+                &mut line_prefix.writer(&mut TeeWriter::new(&mut buffer.writer("err"), &mut watcher_funnel.writer("")), "err"),
+                report_usage,
+            ).await
+        };
+        drop(watcher_funnel);
+        let output = buffer.take().await;
+        if status.is_ok() || !retry.should_retry(attempt, output_matched.load(Ordering::Acquire)) {
+            break (status, usage, output);
         }
-        status
+        let delay = retry.delay_after(attempt);
+        monitor_writer
+            .write_line(format!("{} attempt {}/{} failed (code {}) in {}, retrying in {}",
+                current_time_user_str(), attempt, retry.max_attempts(), status.code(),
+                format_duration(attempt_t0.elapsed().as_millis()), format_duration(delay.as_millis())))
+            .await;
+        sleep(delay).await;
     };
-    let duration_ms = t0.elapsed().as_millis();
-    let duration_fmtd = if duration_ms > 120_000 {
-        format!("{} ms ({} min)", duration_ms, (duration_ms as f64 / 60_000.0).round() as u64)
-    } else if duration_ms > 10_000 {
-        format!("{} ms ({} s)", duration_ms, (duration_ms as f64 / 1000.0).round() as u64)
-    } else {
-        format!("{} ms", duration_ms)
+    let duration = t0.elapsed();
+    if !output_on_success && status.is_err() {
+        eprintln!("printing all output because process failed");
+        for (stream, line) in &output {
+            if *stream == "err" {
+                err_writer.write_line(line).await
+            } else {
+                output_writer.write_line(line).await
+            }
+        }
+    }
+    let usage_note = usage.map(|usage| format!("; {}", usage)).unwrap_or_default();
+    let timed_out = task.timeout.is_some() && status == ExitStatus::timeout();
+    let (failure_word, failure_short) = if timed_out { ("TIMED OUT", "timeout") } else { ("FAILED", "err") };
+    let attempts_note = if attempt > 1 { format!(" (attempt {}/{})", attempt, retry.max_attempts()) } else { String::new() };
+    let log_note = match log_file {
+        Some(log_file) => match write_log(log_file, &output) {
+            Ok(()) => format!("; log in {}", log_file.to_string_lossy()),
+            Err(err) => {
+                eprintln!("could not write mon log: {}", err);
+                String::new()
+            }
+        },
+        None => String::new(),
     };
+    let duration_ms = duration.as_millis();
+    let output_tail = output[output.len().saturating_sub(HISTORY_TAIL_LINES)..].iter()
+        .map(|(_, line)| line.clone())
+        .collect::<Vec<_>>();
+    if record_history {
+        let entry = HistoryEntry {
+            task: task.clone(),
+            start,
+            duration_ms: duration_ms as u64,
+            exit_code: status.code(),
+            output_tail: output_tail.clone(),
+        };
+        if let Err(err) = history_path().and_then(|pth| append_history(&pth, &entry)) {
+            eprintln!("could not record mon history: {}", err);
+        }
+    }
+    let duration_fmtd = format_duration(duration_ms);
     let time_fmtd = current_time_user_str();
     let details = if timing && status.is_ok() {
         monitor_writer
-            .write_line(format!("{} success: took {} to run {}",
-                time_fmtd, duration_fmtd, cmd_str))
+            .write_line(format!("{} success: took {} to run {}{}{}",
+                time_fmtd, duration_fmtd, cmd_str, attempts_note, usage_note))
             .await;
-        format!("took {} to run {}", duration_fmtd, cmd_str)
+        format!("took {} to run {}{}{}", duration_fmtd, cmd_str, attempts_note, usage_note)
     } else if timing && !status.is_ok() {
         eprintln!(
-            "{} FAILED command {} in {} (code {})",
-            time_fmtd, cmd_str, duration_fmtd, status.code()
+            "{} {} command {} in {} (code {}){}{}{}",
+            time_fmtd, failure_word, cmd_str, duration_fmtd, status.code(), attempts_note, usage_note, log_note
         );
-        format!("err {} in {} for {}", status.code(), duration_fmtd, cmd_str)
+        format!("{} {} in {} for {}{}{}", failure_short, status.code(), duration_fmtd, cmd_str, attempts_note, usage_note)
     } else if !timing && !status.is_ok() {
-        eprintln!("{} FAILED command {} (code {})",
-            time_fmtd, cmd_str, status.code());
-        format!("err {} for {}", status.code(), cmd_str)
+        eprintln!("{} {} command {} (code {}){}{}{}",
+            time_fmtd, failure_word, cmd_str, status.code(), attempts_note, usage_note, log_note);
+        format!("{} {} for {}{}{}", failure_short, status.code(), cmd_str, attempts_note, usage_note)
     } else {
-        format!("{} finished {}",
-            time_fmtd, cmd_str)
+        format!("{} finished {}{}{}",
+            time_fmtd, cmd_str, attempts_note, usage_note)
     };
     debug!("{}", &details);
+    if hooks.should_run(status.is_ok(), duration) {
+        hooks.run(&HookPayload::new(task, start, duration_ms as u64, status.code(), output_tail)).await;
+    }
     if let Err(err) = sound_notification(sound_success, sound_failure, status.is_ok(), details).await {
         eprintln!("notification sound problem: {}", err);
         return ExitStatus::err();
@@ -154,6 +220,45 @@ pub async fn mon_task(
     status
 }
 
+async fn execute(
+    task: &Task,
+    out_writer: &mut impl LineWriter,
+    err_writer: &mut impl LineWriter,
+    report_usage: bool,
+) -> (ExitStatus, Option<ResourceUsage>) {
+    if report_usage {
+        task.execute_with_usage_nomonitor(out_writer, err_writer).await
+    } else {
+        (task.execute_with_stdout_nomonitor(out_writer, err_writer).await, None)
+    }
+}
+
+/// Write the combined stdout and stderr, in the order they were produced.
+fn write_log(pth: &Path, output: &[(&str, String)]) -> Result<(), String> {
+    if let Some(dir) = pth.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|err| {
+            format!("failed to create log directory '{}', error {}", dir.to_string_lossy(), err)
+        })?;
+    }
+    let mut content = output.iter().map(|(_, line)| line.as_str()).join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    fs::write(pth, content)
+        .map_err(|err| format!("failed to write log '{}', error {}", pth.to_string_lossy(), err))
+}
+
+/// Format a duration in milliseconds, adding seconds or minutes for long durations.
+pub fn format_duration(duration_ms: u128) -> String {
+    if duration_ms > 120_000 {
+        format!("{} ms ({} min)", duration_ms, (duration_ms as f64 / 60_000.0).round() as u64)
+    } else if duration_ms > 10_000 {
+        format!("{} ms ({} s)", duration_ms, (duration_ms as f64 / 1000.0).round() as u64)
+    } else {
+        format!("{} ms", duration_ms)
+    }
+}
+
 fn unique_envs<T>(items: &[T]) -> Vec<T>
         where T: Eq + Hash + Clone + Display {
     let mut seen = HashSet::new();
@@ -1,9 +1,15 @@
+use ::std::path::PathBuf;
+use ::std::time::Duration;
 
 use ::clap::Parser;
+use ::parse_duration0::parse as parse_dur;
+use ::regex::Regex;
 
 use crate::common::CommandArgs;
+use crate::common::PrefixTemplate;
+use crate::observe::HookOn;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     name = "mon",
     about = "Log the command, the outcome, timings and play a sound."
@@ -18,15 +24,18 @@ pub struct MonArgs {
     /// Do not report timing, and suppress status line when successful.
     #[arg(short = 't', long)]
     pub no_timing: bool,
-    /// Play a sound when the command succeeds.
+    /// Play a sound when the command succeeds. Uses macOS, Linux or terminal bell notifications based on the platform, or env MON_NOTIFY_BACKEND. Set env NO_SOUND to disable.
     #[arg(short = 's', long = "ok-sound")]
     pub sound_success: bool,
     /// Play a sound when the command fails.
     #[arg(short = 'S', long = "fail-sound")]
     pub sound_failure: bool,
-    /// Prefix each line. Can use '%{date}', '%{time}' and '%{nr}' placeholders.
-    #[arg(short = 'p', long)]
-    pub prefix: Option<String>,
+    /// Report peak memory, cpu time and disk io of the command in the status line.
+    #[arg(short = 'u', long)]
+    pub usage: bool,
+    /// Prefix each output line. Can use '%{date}', '%{time}', '%{nr}', '%{elapsed}', '%{stream}' (out or err) and '%{cmd}' placeholders.
+    #[arg(value_parser = PrefixTemplate::parse, short = 'p', long)]
+    pub prefix: Option<PrefixTemplate>,
     /// Log command and timing to stdout instead of stderr
     #[arg(short = 'x', long)]
     pub use_stdout: bool,
@@ -36,6 +45,51 @@ pub struct MonArgs {
     /// Print environment variable with this name (can be repeated).
     #[arg(short = 'e', long, requires = "full_command")]
     pub print_envs: Vec<String>,
+    /// Save the combined stdout and stderr to this file; the path is shown if the command fails.
+    #[arg(short = 'l', long = "log-file")]
+    pub log_file: Option<PathBuf>,
+    /// Stop the command (and processes it started) if it takes longer than this, e.g. "30 min". Runs it in its own process group.
+    #[arg(value_parser = parse_dur, short = 'T', long)]
+    pub timeout: Option<Duration>,
+    /// After the timeout, how long to wait after SIGTERM before sending SIGKILL.
+    #[arg(value_parser = parse_dur, long = "timeout-grace", default_value = "5s", requires = "timeout")]
+    pub timeout_grace: Duration,
+    /// Keep running, and run the command again whenever files under this path change (can be repeated). Respects .gitignore.
+    #[arg(short = 'w', long)]
+    pub watch: Vec<PathBuf>,
+    /// When files change while the command is running, stop it and start again.
+    #[arg(long, requires = "watch")]
+    pub restart: bool,
+    /// Wait until files stopped changing for this long before running again.
+    #[arg(value_parser = parse_dur, long, default_value = "300ms", requires = "watch")]
+    pub debounce: Duration,
+    /// Run the command again up to this many times if it fails.
+    #[arg(short = 'r', long, default_value = "0")]
+    pub retry: u32,
+    /// How long to wait before retrying, e.g. "5s".
+    #[arg(value_parser = parse_dur, long = "retry-delay", default_value = "1s", requires = "retry")]
+    pub retry_delay: Duration,
+    /// Double the delay after every failed attempt.
+    #[arg(long = "retry-exponential", requires = "retry")]
+    pub retry_exponential: bool,
+    /// Only retry if the output of the failed attempt matches this pattern (can be repeated).
+    #[arg(long = "retry-on", requires = "retry")]
+    pub retry_on: Vec<Regex>,
+    /// Record the run (command, directory, timing, exit code and output tail) in the history, see `mon_history`. Can also be set using env MON_HISTORY
+    #[arg(short = 'H', long)]
+    pub history: bool,
+    /// Shell command to run when done, receiving a json description of the run on stdin (can be repeated).
+    #[arg(long = "hook-cmd")]
+    pub hook_cmds: Vec<String>,
+    /// Url to POST a json description of the run to when done (can be repeated).
+    #[arg(long = "hook-url")]
+    pub hook_urls: Vec<String>,
+    /// Only run hooks after success or failure.
+    #[arg(value_enum, long = "hook-on", default_value = "always")]
+    pub hook_on: HookOn,
+    /// Only run hooks if the command took at least this long, e.g. "5 min".
+    #[arg(value_parser = parse_dur, long = "hook-min-duration", default_value = "0s")]
+    pub hook_min_duration: Duration,
     #[command(subcommand)]
     pub cmd: CommandArgs,
 }
@@ -43,5 +97,12 @@ pub struct MonArgs {
 #[test]
 fn test_cli_args() {
     MonArgs::try_parse_from(&["cmd", "ls"]).unwrap();
-    MonArgs::try_parse_from(&["cmd", "-cbtxf", "-sS", "-p=pre", "ls"]).unwrap();
+    MonArgs::try_parse_from(&["cmd", "--hook-cmd", "cat", "--hook-url", "http://localhost/x", "--hook-on", "failure", "ls"]).unwrap();
+    MonArgs::try_parse_from(&["cmd", "-p=[%{nr} %{stream}]", "ls"]).unwrap();
+    MonArgs::try_parse_from(&["cmd", "-b", "-u", "-l", "/tmp/build.log", "ls"]).unwrap();
+    MonArgs::try_parse_from(&["cmd", "-w", "src", "-w", "test", "--restart", "--debounce", "500ms", "mvnw", "-t"]).unwrap();
+    MonArgs::try_parse_from(&["cmd", "-T", "30 min", "--timeout-grace", "10s", "ls"]).unwrap();
+    MonArgs::try_parse_from(&["cmd", "-r", "3", "--retry-delay", "2s", "--retry-exponential", "--retry-on", "timed? out", "ls"]).unwrap();
+    assert!(MonArgs::try_parse_from(&["cmd", "-p=%{nope}", "ls"]).is_err());
+    MonArgs::try_parse_from(&["cmd", "-cbtxH", "-sS", "-p=pre", "ls"]).unwrap();
 }
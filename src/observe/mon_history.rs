@@ -0,0 +1,157 @@
+use ::std::fs;
+use ::std::fs::OpenOptions;
+use ::std::io::Write;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::process;
+
+use ::log::debug;
+use ::serde::Deserialize;
+use ::serde::Serialize;
+use ::time::OffsetDateTime;
+
+use crate::common::Task;
+
+/// Number of output lines stored with each run.
+pub const HISTORY_TAIL_LINES: usize = 20;
+const DATA_VERSION: u32 = 1;
+/// When the history grows beyond this size, the oldest half is removed.
+const MAX_HISTORY_BYTES: u64 = 16 * 1024 * 1024;
+
+/// One monitored run, stored as a json line in the history file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub task: Task,
+    pub start: OffsetDateTime,
+    pub duration_ms: u64,
+    pub exit_code: u8,
+    pub output_tail: Vec<String>,
+}
+
+impl HistoryEntry {
+    pub fn is_ok(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+pub fn history_path() -> Result<PathBuf, String> {
+    let mut pth = dirs::cache_dir().ok_or_else(|| "failed to find cache directory".to_owned())?;
+    pth.push(format!("mon_history_v{}", DATA_VERSION));
+    pth.push("history.jsonl");
+    Ok(pth)
+}
+
+pub fn append_history(pth: &Path, entry: &HistoryEntry) -> Result<(), String> {
+    if let Some(dir) = pth.parent() {
+        fs::create_dir_all(dir).map_err(|err| {
+            format!("failed to create mon history directory '{}', error {}", dir.to_string_lossy(), err)
+        })?;
+    }
+    let mut line = serde_json::to_string(entry)
+        .map_err(|err| format!("failed to serialize mon history entry, error {}", err))?;
+    line.push('\n');
+    // a single small append is atomic, so concurrent runs do not corrupt each other's lines
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(pth)
+        .map_err(|err| format!("failed to open mon history '{}', error {}", pth.to_string_lossy(), err))?;
+    file.write_all(line.as_bytes())
+        .map_err(|err| format!("failed to write mon history '{}', error {}", pth.to_string_lossy(), err))?;
+    let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    if size > MAX_HISTORY_BYTES {
+        truncate_history(pth)?;
+    }
+    Ok(())
+}
+
+/// Remove the oldest half of the history.
+/// Other runs may append while this happens, so anything added after reading is kept too.
+fn truncate_history(pth: &Path) -> Result<(), String> {
+    let content = fs::read_to_string(pth)
+        .map_err(|err| format!("failed to read mon history '{}', error {}", pth.to_string_lossy(), err))?;
+    let lines = content.lines().collect::<Vec<_>>();
+    let mut kept = lines[lines.len() / 2..].join("\n");
+    kept.push('\n');
+    let tmp_pth = pth.with_extension(format!("jsonl.{}.tmp", process::id()));
+    fs::write(&tmp_pth, &kept)
+        .map_err(|err| format!("failed to truncate mon history '{}', error {}", pth.to_string_lossy(), err))?;
+    let appended = fs::read(pth)
+        .map(|current| current.get(content.len()..).map(|extra| extra.to_vec()).unwrap_or_default())
+        .map_err(|err| format!("failed to read mon history '{}', error {}", pth.to_string_lossy(), err))?;
+    if !appended.is_empty() {
+        debug!("{} bytes were appended to mon history while truncating", appended.len());
+        OpenOptions::new()
+            .append(true)
+            .open(&tmp_pth)
+            .and_then(|mut file| file.write_all(&appended))
+            .map_err(|err| format!("failed to truncate mon history '{}', error {}", pth.to_string_lossy(), err))?;
+    }
+    fs::rename(&tmp_pth, pth)
+        .map_err(|err| format!("failed to truncate mon history '{}', error {}", pth.to_string_lossy(), err))?;
+    debug!("truncated mon history from {} to {} entries", lines.len(), lines.len() - lines.len() / 2);
+    Ok(())
+}
+
+/// Read all history entries, oldest first. Lines that cannot be parsed are skipped.
+pub fn read_history(pth: &Path) -> Result<Vec<HistoryEntry>, String> {
+    if !pth.exists() {
+        debug!("no mon history at '{}'", pth.to_string_lossy());
+        return Ok(vec![]);
+    }
+    let content = fs::read_to_string(pth)
+        .map_err(|err| format!("failed to read mon history '{}', error {}", pth.to_string_lossy(), err))?;
+    let mut entries = vec![];
+    for (nr, line) in content.lines().enumerate() {
+        match serde_json::from_str::<HistoryEntry>(line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => debug!("skipping unreadable mon history line #{}, err {}", nr + 1, err),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(exit_code: u8) -> HistoryEntry {
+        HistoryEntry {
+            task: Task::noop(),
+            start: OffsetDateTime::now_utc(),
+            duration_ms: 1200,
+            exit_code,
+            output_tail: vec!["done".to_owned()],
+        }
+    }
+
+    #[test]
+    fn append_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let pth = dir.path().join("sub").join("history.jsonl");
+        assert!(read_history(&pth).unwrap().is_empty());
+        append_history(&pth, &entry(0)).unwrap();
+        append_history(&pth, &entry(1)).unwrap();
+        let mut content = fs::read_to_string(&pth).unwrap();
+        content.push_str("{corrupt\n");
+        fs::write(&pth, content).unwrap();
+        let entries = read_history(&pth).unwrap();
+        assert_eq!(entries, vec![entry_like(&entries[0], 0), entry_like(&entries[1], 1)]);
+    }
+
+    fn entry_like(read: &HistoryEntry, exit_code: u8) -> HistoryEntry {
+        HistoryEntry { start: read.start, ..entry(exit_code) }
+    }
+
+    #[test]
+    fn truncate_keeps_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let pth = dir.path().join("history.jsonl");
+        for code in 0..4 {
+            append_history(&pth, &entry(code)).unwrap();
+        }
+        truncate_history(&pth).unwrap();
+        let codes = read_history(&pth).unwrap().iter().map(|entry| entry.exit_code).collect::<Vec<_>>();
+        assert_eq!(codes, vec![2, 3]);
+    }
+}
@@ -0,0 +1,42 @@
+use ::std::path::PathBuf;
+use ::std::time::Duration;
+
+use ::clap::Parser;
+use ::parse_duration0::parse as parse_dur;
+use ::regex::Regex;
+
+#[derive(Parser, Debug, Default)]
+#[command(
+    name = "mon_history",
+    about = "Show commands recorded by `mon --history`, or statistics about their duration."
+)]
+pub struct MonHistoryArgs {
+    /// Only include runs where the command matches this regular expression.
+    #[arg(short = 'c', long = "cmd")]
+    pub cmd_pattern: Option<Regex>,
+    /// Only include runs in this directory, e.g. '.' for the current one.
+    #[arg(short = 'd', long)]
+    pub dir: Option<PathBuf>,
+    /// Only include failed runs.
+    #[arg(short = 'f', long)]
+    pub failed: bool,
+    /// Only include runs that started this long ago or later, e.g. "4 weeks".
+    #[arg(value_parser = parse_dur, short = 's', long)]
+    pub since: Option<Duration>,
+    /// Show at most this many of the most recent runs (statistics use all matching runs).
+    #[arg(short = 'n', long, default_value = "20")]
+    pub limit: usize,
+    /// Show duration statistics (p50, p95 and the trend per week) instead of individual runs.
+    #[arg(short = 'S', long)]
+    pub stats: bool,
+    /// Show the stored output tail for each run.
+    #[arg(short = 'o', long, conflicts_with = "stats")]
+    pub output: bool,
+}
+
+#[test]
+fn test_cli_args() {
+    MonHistoryArgs::try_parse_from(&["cmd", "-c", "mvnw", "-d", ".", "-s", "30 min", "-S"]).unwrap();
+    MonHistoryArgs::try_parse_from(&["cmd", "-f", "-n", "5", "-o"]).unwrap();
+    assert!(MonHistoryArgs::try_parse_from(&["cmd", "-S", "-o"]).is_err());
+}
@@ -0,0 +1,200 @@
+use ::std::collections::BTreeMap;
+use ::std::path::Path;
+
+use ::itertools::Itertools;
+use ::log::debug;
+use ::time::format_description;
+use ::time::Duration;
+use ::time::OffsetDateTime;
+
+use crate::common::local_offset;
+use crate::observe::mon::format_duration;
+use crate::observe::mon_history::history_path;
+use crate::observe::mon_history::read_history;
+use crate::observe::mon_history::HistoryEntry;
+use crate::observe::MonHistoryArgs;
+
+/// Lines describing the matching runs, or their statistics if `--stats`.
+pub fn mon_history(args: MonHistoryArgs) -> Result<Vec<String>, String> {
+    let entries = read_history(&history_path()?)?;
+    let dir = match &args.dir {
+        Some(dir) => Some(dir.canonicalize()
+            .map_err(|err| format!("could not find directory '{}', error {}", dir.to_string_lossy(), err))?),
+        None => None,
+    };
+    let entries = filter_entries(entries, &args, dir.as_deref(), OffsetDateTime::now_utc());
+    debug!("{} mon history entries match", entries.len());
+    if entries.is_empty() {
+        return Ok(vec!["no matching runs in mon history".to_owned()]);
+    }
+    if args.stats {
+        return Ok(duration_stats(&entries));
+    }
+    let mut lines = vec![];
+    for entry in &entries[entries.len().saturating_sub(args.limit)..] {
+        lines.push(format!(
+            "{}  {:<6}  {:>10}  {}  {}",
+            format_start(entry.start),
+            if entry.is_ok() { "ok".to_owned() } else { format!("err {}", entry.exit_code) },
+            format!("{} ms", entry.duration_ms),
+            entry.task.working_dir.to_string_lossy(),
+            entry.task.as_short_cmd_str(),
+        ));
+        if args.output {
+            for line in &entry.output_tail {
+                lines.push(format!("    {}", line));
+            }
+        }
+    }
+    Ok(lines)
+}
+
+fn filter_entries(
+    entries: Vec<HistoryEntry>,
+    args: &MonHistoryArgs,
+    dir: Option<&Path>,
+    now: OffsetDateTime,
+) -> Vec<HistoryEntry> {
+    let since = args.since.and_then(|since| Duration::try_from(since).ok()).map(|since| now - since);
+    entries.into_iter()
+        .filter(|entry| !args.failed || !entry.is_ok())
+        .filter(|entry| dir.is_none_or(|dir| entry.task.working_dir == dir))
+        .filter(|entry| since.is_none_or(|since| entry.start >= since))
+        .filter(|entry| args.cmd_pattern.as_ref().is_none_or(|re| re.is_match(&entry.task.as_cmd_str())))
+        .collect()
+}
+
+/// Overall count, failures and percentiles, followed by the percentiles per week, to spot trends.
+fn duration_stats(entries: &[HistoryEntry]) -> Vec<String> {
+    let failures = entries.iter().filter(|entry| !entry.is_ok()).count();
+    let mut lines = vec![format!(
+        "{} runs, {} failed; {}",
+        entries.len(),
+        failures,
+        percentiles(entries.iter().map(|entry| entry.duration_ms).collect())
+    )];
+    let mut weeks: BTreeMap<(i32, u8), Vec<&HistoryEntry>> = BTreeMap::new();
+    for entry in entries {
+        let date = local_time(entry.start).date();
+        weeks.entry((date.year(), date.iso_week())).or_default().push(entry);
+    }
+    if weeks.len() > 1 {
+        for ((year, week), week_entries) in &weeks {
+            lines.push(format!(
+                "  {}-w{:02}: {:>4} runs, {}",
+                year,
+                week,
+                week_entries.len(),
+                percentiles(week_entries.iter().map(|entry| entry.duration_ms).collect())
+            ));
+        }
+    }
+    if let Some(trend) = trend(entries) {
+        lines.push(trend);
+    }
+    lines
+}
+
+fn percentiles(mut durations: Vec<u64>) -> String {
+    durations.sort_unstable();
+    format!(
+        "p50 {}, p95 {}, max {}",
+        format_duration(percentile(&durations, 50) as u128),
+        format_duration(percentile(&durations, 95) as u128),
+        format_duration(*durations.last().unwrap_or(&0) as u128),
+    )
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Compare the median duration of the most recent half of the runs to the older half.
+fn trend(entries: &[HistoryEntry]) -> Option<String> {
+    if entries.len() < 4 {
+        return None;
+    }
+    let by_time = entries.iter().sorted_by_key(|entry| entry.start).map(|entry| entry.duration_ms).collect::<Vec<_>>();
+    let (older, newer) = by_time.split_at(by_time.len() / 2);
+    let older_p50 = percentile(&older.iter().copied().sorted().collect::<Vec<_>>(), 50);
+    let newer_p50 = percentile(&newer.iter().copied().sorted().collect::<Vec<_>>(), 50);
+    let change_pct = if older_p50 == 0 {
+        0.0
+    } else {
+        100.0 * (newer_p50 as f64 - older_p50 as f64) / older_p50 as f64
+    };
+    Some(format!(
+        "trend: p50 of newer half is {} vs {} for older half ({:+.0}%)",
+        format_duration(newer_p50 as u128),
+        format_duration(older_p50 as u128),
+        change_pct
+    ))
+}
+
+fn local_time(time: OffsetDateTime) -> OffsetDateTime {
+    time.to_offset(local_offset())
+}
+
+fn format_start(time: OffsetDateTime) -> String {
+    let format = format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]").unwrap();
+    local_time(time).format(&format).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use ::regex::Regex;
+
+    use crate::common::Task;
+
+    use super::*;
+
+    fn entry(cmd: &str, days_ago: i64, duration_ms: u64, exit_code: u8) -> HistoryEntry {
+        HistoryEntry {
+            task: Task::new_split_in_cwd(cmd.split(' ').map(|part| part.to_owned()).collect()),
+            start: OffsetDateTime::now_utc() - Duration::days(days_ago),
+            duration_ms,
+            exit_code,
+            output_tail: vec![],
+        }
+    }
+
+    #[test]
+    fn filters() {
+        let entries = vec![entry("mvnw install", 20, 1000, 0), entry("ls -a", 1, 10, 0), entry("mvnw test", 1, 1000, 1)];
+        let now = OffsetDateTime::now_utc();
+        let args = MonHistoryArgs { cmd_pattern: Some(Regex::new("mvnw").unwrap()), ..MonHistoryArgs::default() };
+        assert_eq!(filter_entries(entries.clone(), &args, None, now).len(), 2);
+        let args = MonHistoryArgs { failed: true, ..MonHistoryArgs::default() };
+        assert_eq!(filter_entries(entries.clone(), &args, None, now).len(), 1);
+        let args = MonHistoryArgs { since: Some(::std::time::Duration::from_secs(7 * 86400)), ..MonHistoryArgs::default() };
+        assert_eq!(filter_entries(entries.clone(), &args, None, now).len(), 2);
+        assert_eq!(filter_entries(entries, &MonHistoryArgs::default(), Some(Path::new("/nonexistent")), now).len(), 0);
+    }
+
+    #[test]
+    fn nearest_rank_percentile() {
+        let values = (1..=20).collect::<Vec<u64>>();
+        assert_eq!(percentile(&values, 50), 10);
+        assert_eq!(percentile(&values, 95), 19);
+        assert_eq!(percentile(&[7], 95), 7);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+
+    #[test]
+    fn slower_trend() {
+        let entries = vec![
+            entry("mvnw", 30, 1000, 0),
+            entry("mvnw", 29, 1000, 0),
+            entry("mvnw", 2, 1500, 0),
+            entry("mvnw", 1, 1500, 0),
+        ];
+        let stats = duration_stats(&entries);
+        assert_eq!(stats[0], "4 runs, 0 failed; p50 1000 ms, p95 1500 ms, max 1500 ms");
+        assert_eq!(stats.last().unwrap(), "trend: p50 of newer half is 1500 ms vs 1000 ms for older half (+50%)");
+    }
+}
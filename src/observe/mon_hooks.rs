@@ -0,0 +1,287 @@
+use ::std::io::Read;
+use ::std::io::Write;
+use ::std::net::TcpStream;
+use ::std::net::ToSocketAddrs;
+use ::std::time::Duration;
+
+use ::clap::ValueEnum;
+use ::log::debug;
+use ::serde::Serialize;
+use ::time::format_description::well_known::Rfc3339;
+use ::time::OffsetDateTime;
+use ::which::which;
+
+use crate::common::StdWriter;
+use crate::common::Task;
+use crate::common::Timeout;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+const HOOK_STOP_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HookOn {
+    /// Run hooks after every run.
+    #[default]
+    Always,
+    /// Only run hooks if the command succeeded.
+    Success,
+    /// Only run hooks if the command failed.
+    Failure,
+}
+
+/// Commands and urls to notify when `mon` finishes a command.
+#[derive(Debug, Clone, Default)]
+pub struct MonHooks {
+    pub commands: Vec<String>,
+    pub urls: Vec<String>,
+    pub on: HookOn,
+    pub min_duration: Duration,
+}
+
+/// Json description of the run, sent to hooks.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookPayload {
+    pub cmd: String,
+    pub args: Vec<String>,
+    pub cwd: String,
+    pub start: String,
+    pub duration_ms: u64,
+    pub exit_code: u8,
+    pub success: bool,
+    pub output_tail: Vec<String>,
+}
+
+impl HookPayload {
+    pub fn new(task: &Task, start: OffsetDateTime, duration_ms: u64, exit_code: u8, output_tail: Vec<String>) -> Self {
+        HookPayload {
+            cmd: task.cmd.clone(),
+            args: task.args.clone(),
+            cwd: task.working_dir.to_string_lossy().into_owned(),
+            start: start.format(&Rfc3339).unwrap_or_default(),
+            duration_ms,
+            exit_code,
+            success: exit_code == 0,
+            output_tail,
+        }
+    }
+}
+
+impl MonHooks {
+    pub fn none() -> Self {
+        MonHooks::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty() && self.urls.is_empty()
+    }
+
+    pub fn should_run(&self, is_success: bool, duration: Duration) -> bool {
+        let outcome_matches = match self.on {
+            HookOn::Always => true,
+            HookOn::Success => is_success,
+            HookOn::Failure => !is_success,
+        };
+        !self.is_empty() && outcome_matches && duration >= self.min_duration
+    }
+
+    /// Run all hooks. Failing hooks are reported but do not affect the outcome of `mon`.
+    pub async fn run(&self, payload: &HookPayload) {
+        let json = match serde_json::to_string(payload) {
+            Ok(json) => json,
+            Err(err) => {
+                eprintln!("mon hook failed, could not serialize payload, error {}", err);
+                return;
+            }
+        };
+        for cmd in &self.commands {
+            debug!("running mon hook command '{}'", cmd);
+            let task = Task::new_in_cwd("sh".to_owned(), Some(json.clone()), vec!["-c".to_owned(), cmd.to_owned()])
+                .with_timeout(Some(Timeout { limit: HTTP_TIMEOUT, grace: HOOK_STOP_GRACE }));
+            let status = task.execute_with_stdout_nomonitor(&mut StdWriter::stderr(), &mut StdWriter::stderr()).await;
+            if status.is_err() {
+                eprintln!("mon hook command '{}' failed with code {}", cmd, status.code());
+            }
+        }
+        for url in &self.urls {
+            debug!("posting to mon hook url '{}'", url);
+            if let Err(err) = post_json(url, &json) {
+                eprintln!("mon hook url '{}' failed: {}", url, err);
+            }
+        }
+    }
+}
+
+/// Send a POST request with json body. Plain http is handled directly, https uses curl.
+fn post_json(url: &str, json: &str) -> Result<(), String> {
+    if url.starts_with("https://") {
+        return post_json_curl(url, json);
+    }
+    let (host, port, path) = parse_http_url(url)?;
+    let addr = (host.as_str(), port).to_socket_addrs()
+        .map_err(|err| format!("could not resolve '{}', error {}", host, err))?
+        .next()
+        .ok_or_else(|| format!("no address found for '{}'", host))?;
+    let host_header = if host.contains(':') { format!("[{}]", host) } else { host.clone() };
+    let mut stream = TcpStream::connect_timeout(&addr, HTTP_TIMEOUT)
+        .map_err(|err| format!("could not connect, error {}", err))?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(HTTP_TIMEOUT)))
+        .map_err(|err| format!("could not set timeout, error {}", err))?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, host_header, port, json.len(), json
+    );
+    stream.write_all(request.as_bytes())
+        .map_err(|err| format!("could not send request, error {}", err))?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)
+        .map_err(|err| format!("could not read response, error {}", err))?;
+    let status_line = response.lines().next().unwrap_or("");
+    let status = status_line.split_whitespace().nth(1).unwrap_or("");
+    if !status.starts_with('2') {
+        return Err(format!("unexpected response '{}'", status_line));
+    }
+    debug!("mon hook url '{}' responded with '{}'", url, status_line);
+    Ok(())
+}
+
+fn post_json_curl(url: &str, json: &str) -> Result<(), String> {
+    if which("curl").is_err() {
+        return Err("https urls need curl, which was not found".to_owned());
+    }
+    let task = Task::new_in_cwd("curl".to_owned(), Some(json.to_owned()), vec![
+        "--silent".to_owned(), "--show-error".to_owned(), "--fail".to_owned(),
+        "--max-time".to_owned(), HTTP_TIMEOUT.as_secs().to_string(),
+        "-H".to_owned(), "Content-Type: application/json".to_owned(),
+        "--data-binary".to_owned(), "@-".to_owned(),
+        url.to_owned(),
+    ]);
+    let status = task.execute_sync(false);
+    if status.is_err() {
+        return Err(format!("curl failed with code {}", status.code()));
+    }
+    Ok(())
+}
+
+fn parse_http_url(url: &str) -> Result<(String, u16, String), String> {
+    let rest = url.strip_prefix("http://")
+        .ok_or_else(|| format!("url '{}' should start with http:// or https://", url))?;
+    let (host_port, path) = match rest.find('/') {
+        Some(ix) => (&rest[..ix], &rest[ix..]),
+        None => (rest, "/"),
+    };
+    // ipv6 hosts are in brackets, because they contain colons themselves
+    let (host, port) = match host_port.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, after) = bracketed.split_once(']')
+                .ok_or_else(|| format!("unclosed '[' in url '{}'", url))?;
+            match after {
+                "" => (host, None),
+                _ => match after.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => return Err(format!("unexpected text after ']' in url '{}'", url)),
+                },
+            }
+        }
+        None => match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host_port, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port.parse::<u16>()
+            .map_err(|err| format!("invalid port in url '{}', error {}", url, err))?,
+        None => 80,
+    };
+    if host.is_empty() {
+        return Err(format!("no host in url '{}'", url));
+    }
+    Ok((host.to_owned(), port, path.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::fs;
+    use ::std::io::BufRead;
+    use ::std::io::BufReader;
+    use ::std::net::TcpListener;
+    use ::std::thread;
+
+    use super::*;
+
+    fn payload() -> HookPayload {
+        HookPayload::new(&Task::noop(), OffsetDateTime::now_utc(), 1500, 1, vec!["oops".to_owned()])
+    }
+
+    #[test]
+    fn filter_outcome_and_duration() {
+        let hooks = MonHooks {
+            commands: vec!["true".to_owned()],
+            on: HookOn::Failure,
+            min_duration: Duration::from_secs(1),
+            ..MonHooks::none()
+        };
+        assert!(hooks.should_run(false, Duration::from_secs(2)));
+        assert!(!hooks.should_run(true, Duration::from_secs(2)));
+        assert!(!hooks.should_run(false, Duration::from_millis(500)));
+        assert!(!MonHooks::none().should_run(false, Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn url_parsing() {
+        assert_eq!(parse_http_url("http://localhost:8080/hook?x=1"), Ok(("localhost".to_owned(), 8080, "/hook?x=1".to_owned())));
+        assert_eq!(parse_http_url("http://example.com"), Ok(("example.com".to_owned(), 80, "/".to_owned())));
+        assert!(parse_http_url("ftp://example.com").is_err());
+        assert!(parse_http_url("http://:80/").is_err());
+        assert_eq!(parse_http_url("http://[::1]:8080/x"), Ok(("::1".to_owned(), 8080, "/x".to_owned())));
+        assert_eq!(parse_http_url("http://[::1]/x"), Ok(("::1".to_owned(), 80, "/x".to_owned())));
+        assert!(parse_http_url("http://[::1/x").is_err());
+        assert!(parse_http_url("http://[::1]8080/x").is_err());
+    }
+
+    #[async_std::test]
+    async fn command_receives_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_pth = dir.path().join("payload.json");
+        let hooks = MonHooks {
+            commands: vec![format!("cat > '{}'", out_pth.to_string_lossy())],
+            ..MonHooks::none()
+        };
+        hooks.run(&payload()).await;
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out_pth).unwrap()).unwrap();
+        assert_eq!(json["exit_code"], 1);
+        assert_eq!(json["success"], false);
+        assert_eq!(json["output_tail"][0], "oops");
+    }
+
+    #[test]
+    fn post_to_local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_len = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some(len) = header.to_lowercase().strip_prefix("content-length:") {
+                    content_len = len.trim().parse::<usize>().unwrap();
+                }
+            }
+            let mut body = vec![0; content_len];
+            reader.read_exact(&mut body).unwrap();
+            reader.into_inner().write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            (request_line, String::from_utf8(body).unwrap())
+        });
+        post_json(&format!("http://127.0.0.1:{}/hook", port), "{\"a\":1}").unwrap();
+        let (request_line, body) = server.join().unwrap();
+        assert_eq!(request_line.trim(), "POST /hook HTTP/1.1");
+        assert_eq!(body, "{\"a\":1}");
+    }
+}
@@ -0,0 +1,74 @@
+use ::std::path::PathBuf;
+
+use crate::common::PrefixTemplate;
+use crate::observe::mon_args::MonArgs;
+use crate::observe::mon_hooks::MonHooks;
+use crate::observe::mon_retry::MonRetry;
+
+/// How `mon` runs and reports a task. The default shows the full command, output and timing, without extras.
+#[derive(Debug, Clone)]
+pub struct MonOptions {
+    pub print_cmd: bool,
+    pub full_cmd: bool,
+    pub output_on_success: bool,
+    pub timing: bool,
+    pub sound_success: bool,
+    pub sound_failure: bool,
+    pub print_envs: Vec<String>,
+    pub record_history: bool,
+    pub hooks: MonHooks,
+    pub prefix: PrefixTemplate,
+    pub log_file: Option<PathBuf>,
+    pub report_usage: bool,
+    pub retry: MonRetry,
+}
+
+impl Default for MonOptions {
+    fn default() -> Self {
+        MonOptions {
+            print_cmd: true,
+            full_cmd: true,
+            output_on_success: true,
+            timing: true,
+            sound_success: false,
+            sound_failure: false,
+            print_envs: vec![],
+            record_history: false,
+            hooks: MonHooks::none(),
+            prefix: PrefixTemplate::none(),
+            log_file: None,
+            report_usage: false,
+            retry: MonRetry::none(),
+        }
+    }
+}
+
+impl From<&MonArgs> for MonOptions {
+    fn from(args: &MonArgs) -> Self {
+        MonOptions {
+            print_cmd: !args.no_print_cmd,
+            full_cmd: args.full_command,
+            output_on_success: !args.no_output_on_success,
+            timing: !args.no_timing,
+            sound_success: args.sound_success,
+            sound_failure: args.sound_failure,
+            print_envs: args.print_envs.clone(),
+            record_history: args.history,
+            hooks: MonHooks {
+                commands: args.hook_cmds.clone(),
+                urls: args.hook_urls.clone(),
+                on: args.hook_on,
+                min_duration: args.hook_min_duration,
+            },
+            prefix: args.prefix.clone().unwrap_or_else(PrefixTemplate::none),
+            log_file: args.log_file.clone(),
+            report_usage: args.usage,
+            retry: MonRetry {
+                count: args.retry,
+                delay: args.retry_delay,
+                exponential: args.retry_exponential,
+                only_on: args.retry_on.clone(),
+            },
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use ::std::time::Duration;
+
+use ::regex::Regex;
+use ::smallvec::SmallVec;
+
+/// When and how often `mon` runs a failed command again.
+#[derive(Debug, Clone, Default)]
+pub struct MonRetry {
+    /// Maximum number of retries after the first attempt.
+    pub count: u32,
+    pub delay: Duration,
+    /// Double the delay after every attempt.
+    pub exponential: bool,
+    /// Only retry if the output of the failed attempt matches one of these, or always if empty.
+    pub only_on: Vec<Regex>,
+}
+
+impl MonRetry {
+    pub fn none() -> Self {
+        MonRetry::default()
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.count.saturating_add(1)
+    }
+
+    /// Patterns for watching the output of an attempt.
+    pub fn patterns(&self) -> SmallVec<[Regex; 1]> {
+        self.only_on.iter().cloned().collect()
+    }
+
+    pub fn should_retry(&self, attempt: u32, output_matched: bool) -> bool {
+        attempt < self.max_attempts() && (self.only_on.is_empty() || output_matched)
+    }
+
+    /// How long to wait after the given (1-based) attempt failed.
+    pub fn delay_after(&self, attempt: u32) -> Duration {
+        if !self.exponential {
+            return self.delay;
+        }
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1).min(16));
+        self.delay.saturating_mul(factor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let retry = MonRetry { count: 5, delay: Duration::from_millis(100), ..MonRetry::none() };
+        assert_eq!(retry.delay_after(3), Duration::from_millis(100));
+        let retry = MonRetry { exponential: true, ..retry };
+        assert_eq!(retry.delay_after(1), Duration::from_millis(100));
+        assert_eq!(retry.delay_after(3), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn retry_conditions() {
+        assert!(!MonRetry::none().should_retry(1, true));
+        let retry = MonRetry { count: 2, ..MonRetry::none() };
+        assert!(retry.should_retry(2, false));
+        assert!(!retry.should_retry(3, false));
+        let retry = MonRetry { only_on: vec![Regex::new("timeout").unwrap()], ..retry };
+        assert!(!retry.should_retry(1, false));
+        assert!(retry.should_retry(1, true));
+    }
+}
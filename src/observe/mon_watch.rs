@@ -0,0 +1,155 @@
+use ::std::collections::HashMap;
+use ::std::path::PathBuf;
+use ::std::thread;
+use ::std::thread::sleep;
+use ::std::time::Duration;
+use ::std::time::Instant;
+use ::std::time::SystemTime;
+
+use ::async_std::task::block_on;
+use ::ignore::WalkBuilder;
+use ::log::debug;
+
+use crate::common::current_time_user_str;
+use crate::common::LineWriter;
+use crate::common::ProcessGroup;
+use crate::common::Task;
+use crate::observe::mon::mon_task;
+use crate::observe::MonArgs;
+use crate::observe::MonOptions;
+use crate::ExitStatus;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+/// Modification time and size of every file under the roots that is not ignored.
+#[derive(Debug, PartialEq, Eq)]
+struct FileSnapshot {
+    files: HashMap<PathBuf, (SystemTime, u64)>,
+}
+
+impl FileSnapshot {
+    fn take(roots: &[PathBuf]) -> Self {
+        let mut files = HashMap::new();
+        let Some((first, rest)) = roots.split_first() else {
+            return FileSnapshot { files };
+        };
+        let mut walker = WalkBuilder::new(first);
+        for root in rest {
+            walker.add(root);
+        }
+        walker.git_ignore(true).require_git(false);
+        for entry in walker.build() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    debug!("skipping file while watching, err {}", err);
+                    continue;
+                }
+            };
+            if let Ok(meta) = entry.metadata() {
+                if meta.is_file() {
+                    let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    files.insert(entry.into_path(), (modified, meta.len()));
+                }
+            }
+        }
+        FileSnapshot { files }
+    }
+
+    /// Update the snapshot, returning whether anything changed.
+    fn refresh(&mut self, roots: &[PathBuf]) -> bool {
+        let current = FileSnapshot::take(roots);
+        if current == *self {
+            return false;
+        }
+        *self = current;
+        true
+    }
+
+    /// Wait until nothing changed for the debounce duration, so that a burst of changes causes one run.
+    fn settle(&mut self, roots: &[PathBuf], debounce: Duration) {
+        let mut last_change = Instant::now();
+        while last_change.elapsed() < debounce {
+            sleep(POLL_INTERVAL.min(debounce));
+            if self.refresh(roots) {
+                last_change = Instant::now();
+            }
+        }
+    }
+}
+
+/// Run the task, and run it again every time files under the watched roots change. Never returns.
+pub fn mon_watch(
+    task: &Task,
+    opts: &MonOptions,
+    args: &MonArgs,
+    output_writer: &mut impl LineWriter,
+    monitor_writer: &mut impl LineWriter,
+) -> ExitStatus {
+    let roots = args.watch.clone();
+    let group = ProcessGroup::new();
+    let task = &task.clone().with_process_group(group.clone());
+    let mut snapshot = FileSnapshot::take(&roots);
+    debug!("watching {} files under {} roots", snapshot.files.len(), roots.len());
+    loop {
+        let mut restarted = false;
+        thread::scope(|scope| {
+            let run = scope.spawn(|| block_on(mon_task(task, output_writer, monitor_writer, opts)));
+            while !run.is_finished() {
+                sleep(POLL_INTERVAL);
+                if args.restart && snapshot.refresh(&roots) {
+                    snapshot.settle(&roots, args.debounce);
+                    eprintln!("{}: files changed, stopping the current run", current_time_user_str());
+                    stop_run(&run, &group);
+                    restarted = true;
+                    break;
+                }
+            }
+            let status = run.join().expect("thread panic");
+            debug!("watched run finished with status {}", status);
+        });
+        if !restarted {
+            while !snapshot.refresh(&roots) {
+                sleep(POLL_INTERVAL);
+            }
+            snapshot.settle(&roots, args.debounce);
+        }
+        block_on(monitor_writer.write_line(format!("{}: files changed, running again", current_time_user_str())));
+    }
+}
+
+/// Stop the process group of the current run, first with SIGTERM and then SIGKILL.
+fn stop_run(run: &thread::ScopedJoinHandle<'_, ExitStatus>, group: &ProcessGroup) {
+    group.signal(false);
+    let t0 = Instant::now();
+    while !run.is_finished() {
+        if t0.elapsed() > STOP_GRACE {
+            eprintln!("run did not stop within {} ms, killing it", STOP_GRACE.as_millis());
+            group.signal(true);
+            break;
+        }
+        sleep(Duration::from_millis(20));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::fs;
+
+    use super::*;
+
+    #[test]
+    fn snapshot_respects_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = vec![dir.path().to_owned()];
+        fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir.path().join("main.rs"), "fn main() {}").unwrap();
+        let mut snapshot = FileSnapshot::take(&roots);
+        assert!(!snapshot.refresh(&roots));
+        fs::write(dir.path().join("build.log"), "ignored").unwrap();
+        assert!(!snapshot.refresh(&roots));
+        fs::write(dir.path().join("main.rs"), "fn main() { println!() }").unwrap();
+        assert!(snapshot.refresh(&roots));
+    }
+}
@@ -1,44 +1,178 @@
-#![allow(unused)]  //TODO @mark: TEMPORARY! REMOVE THIS!
+use ::std::thread;
 
+use ::async_std::task::block_on;
+use ::async_trait::async_trait;
+
+use crate::common::CommandArgs;
+use crate::common::FunnelFactory;
 use crate::common::LineReader;
 use crate::common::LineWriter;
+use crate::common::ProcessGroup;
 use crate::common::StdWriter;
+use crate::common::Task;
 use crate::ExitStatus;
 use crate::observe::chained;
+use crate::observe::piped_args::PipeFail;
 use crate::observe::piped_args::PipedArgs;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StageResult {
+    status: ExitStatus,
+    line_count: u64,
+    /// Stopped because the next command no longer reads its output, which is not a failure (like SIGPIPE).
+    pipe_closed: bool,
+}
+
 pub async fn piped(
     args: PipedArgs,
     outer_reader: &mut impl LineReader,
     outer_writer: &mut impl LineWriter,
 ) -> ExitStatus {
-    assert!(!args.stderr);
-    let (source_cmd, sink_cmd) = args.cmds.split_once_at(&args.separator);
+    let tasks = match split_tasks(args.cmds.clone(), &args.separator) {
+        Ok(tasks) => tasks,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitStatus::err();
+        }
+    };
     let buffer_size = args.pipe_buffer_size.try_into().unwrap_or(usize::MAX);
-    let (mut chain_write, chain_read) = chained(buffer_size);
-    // let (source_res, sink_res) = join!(
-    //     source_cmd.into_task().execute_with_stdout_nomonitor(outer_reader, chain_write, &mut StdWriter::stderr()),
-    //     sink_cmd.into_task().execute_with_stdout_nomonitor(chain_read, outer_writer, &mut StdWriter::stderr()),
-    // ).await;
-    // ExitStatus::max(source_res, sink_res)
-    unimplemented!();  // ^
-    unimplemented!();  //TODO @mark:
-    // let (source_res, sink_res) = join!(
-    //     source_cmd.into_task().execute_with_stdout_nomonitor(outer_reader, chain_write, &mut StdWriter::stderr()),
-    //     sink_cmd.into_task().execute_with_stdout_nomonitor(chain_read, outer_writer, &mut StdWriter::stderr()),
-    // ).await;
-    // ExitStatus::max(source_res, sink_res)
+    let (chain_writers, chain_readers): (Vec<_>, Vec<_>) =
+        (1..tasks.len()).map(|_| chained(buffer_size)).unzip();
+    // the output that is not piped, and the output of the last command, go to the outer writer
+    let funnel = FunnelFactory::new(outer_writer);
+    let pipe_stderr = args.stderr;
+    let last_ix = tasks.len() - 1;
+    // Tasks block while running, so use threads to let all commands in the pipeline progress
+    let results = thread::scope(|scope| {
+        let mut handles = Vec::with_capacity(tasks.len());
+        let mut chain_writers = chain_writers.into_iter();
+        let mut chain_readers = chain_readers.into_iter();
+        let mut outer_reader = Some(outer_reader);
+        for (ix, task) in tasks.iter().enumerate() {
+            let mut unpiped_writer = funnel.writer("");
+            let handle = if ix == last_ix {
+                let mut last_writer = funnel.writer("");
+                match outer_reader.take() {
+                    Some(reader) => scope.spawn(move || run_stage(task, reader, &mut last_writer, &mut StdWriter::stderr(), false)),
+                    None => {
+                        let mut reader = chain_readers.next().unwrap();
+                        scope.spawn(move || run_stage(task, &mut reader, &mut last_writer, &mut StdWriter::stderr(), false))
+                    }
+                }
+            } else {
+                // dropping the chain writer at the end of the thread signals the end of input to the next command
+                let process = ProcessGroup::new();
+                let task = task.clone().with_process_group(process.clone());
+                let mut writer = chain_writers.next().unwrap().stop_on_close(process);
+                match outer_reader.take() {
+                    Some(reader) => scope.spawn(move || {
+                        let result = run_stage(&task, reader, &mut writer, &mut unpiped_writer, pipe_stderr);
+                        StageResult { pipe_closed: writer.is_closed(), ..result }
+                    }),
+                    None => {
+                        let mut reader = chain_readers.next().unwrap();
+                        scope.spawn(move || {
+                            let result = run_stage(&task, &mut reader, &mut writer, &mut unpiped_writer, pipe_stderr);
+                            StageResult { pipe_closed: writer.is_closed(), ..result }
+                        })
+                    }
+                }
+            };
+            handles.push(handle);
+        }
+        handles.into_iter()
+            .map(|handle| handle.join().expect("thread panic"))
+            .collect::<Vec<_>>()
+    });
+    if args.summary {
+        for (ix, (task, result)) in tasks.iter().zip(&results).enumerate() {
+            eprintln!(
+                "command {}/{} code {}{}, {} lines {}: {}",
+                ix + 1,
+                tasks.len(),
+                result.status.code(),
+                if result.pipe_closed { " (stopped, output no longer read)" } else { "" },
+                result.line_count,
+                if ix == last_ix { "output" } else { "piped" },
+                task.as_short_cmd_str()
+            );
+        }
+    }
+    pipeline_status(&results, args.pipefail)
+}
+
+fn split_tasks(cmds: CommandArgs, separator: &str) -> Result<Vec<Task>, String> {
+    let parts = cmds.split_all_at(separator)
+        .into_iter()
+        .map(|part| part.unpack())
+        .collect::<Vec<_>>();
+    if let Some(ix) = parts.iter().position(|part| part.is_empty()) {
+        return Err(format!(
+            "command {} of {} is empty; separator is '{}'",
+            ix + 1,
+            parts.len(),
+            separator
+        ));
+    }
+    Ok(parts.into_iter().map(Task::new_split_in_cwd).collect())
+}
+
+/// Runs one command, sending stdout (or stderr if `pipe_stderr`) to `piped_writer` and the other stream to `unpiped_writer`.
+fn run_stage(
+    task: &Task,
+    reader: &mut impl LineReader,
+    piped_writer: &mut impl LineWriter,
+    unpiped_writer: &mut impl LineWriter,
+    pipe_stderr: bool,
+) -> StageResult {
+    let mut counter = CountingWriter::new(piped_writer);
+    let status = if pipe_stderr {
+        block_on(task.execute_with_inouterr_nomonitor(reader, unpiped_writer, &mut counter))
+    } else {
+        block_on(task.execute_with_inouterr_nomonitor(reader, &mut counter, unpiped_writer))
+    };
+    StageResult { status, line_count: counter.count, pipe_closed: false }
+}
+
+fn pipeline_status(results: &[StageResult], pipefail: PipeFail) -> ExitStatus {
+    let mut statuses = results.iter()
+        .map(|result| if result.pipe_closed { ExitStatus::ok() } else { result.status });
+    match pipefail {
+        PipeFail::Any => statuses.fold(ExitStatus::ok(), ExitStatus::max),
+        PipeFail::First => statuses
+            .find(|status| status.is_err())
+            .unwrap_or_else(ExitStatus::ok),
+        PipeFail::Last => statuses.next_back().unwrap_or_else(ExitStatus::ok),
+    }
+}
+
+#[derive(Debug)]
+struct CountingWriter<'a, W: LineWriter> {
+    delegate: &'a mut W,
+    count: u64,
+}
+
+impl<'a, W: LineWriter> CountingWriter<'a, W> {
+    fn new(delegate: &'a mut W) -> Self {
+        CountingWriter { delegate, count: 0 }
+    }
+}
+
+#[async_trait]
+impl<'a, W: LineWriter> LineWriter for CountingWriter<'a, W> {
+    async fn write_line(&mut self, line: impl AsRef<str> + Send) {
+        self.count += 1;
+        self.delegate.write_line(line).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::common::CollectorWriter;
-    use crate::common::CommandArgs;
     use crate::common::VecReader;
 
     use super::*;
 
-    #[ignore]  //TODO @mverleg: not working yet
     #[async_std::test]
     async fn test_add() {
         let mut writer = CollectorWriter::new();
@@ -46,6 +180,8 @@ mod tests {
             separator: "//".to_string(),
             stderr: false,
             pipe_buffer_size: 4,
+            pipefail: PipeFail::Any,
+            summary: false,
             cmds: CommandArgs::Cmd(vec![
                 "echo".to_owned(),
                 "-n".to_owned(),
@@ -65,4 +201,137 @@ mod tests {
         assert_eq!(output.len(), 1);
         assert_eq!(output[0], "2");
     }
+
+    #[async_std::test]
+    async fn pipe_stdin_and_stderr() {
+        let mut writer = CollectorWriter::new();
+        let args = PipedArgs {
+            separator: "//".to_string(),
+            stderr: true,
+            pipe_buffer_size: 2,
+            pipefail: PipeFail::Any,
+            summary: false,
+            cmds: CommandArgs::Cmd(vec![
+                "sh".to_owned(),
+                "-c".to_owned(),
+                "while read line; do echo \"err $line\" 1>&2; done; echo out".to_owned(),
+                "//".to_owned(),
+                "grep".to_owned(),
+                "-c".to_owned(),
+                "err".to_owned(),
+            ]),
+        };
+        let input = (0..20).map(|nr| nr.to_string()).collect::<Vec<_>>();
+        let res = piped(args, &mut VecReader::new(input), &mut writer).await;
+        assert!(res.is_ok());
+        let mut output = writer.lines().snapshot().await.clone();
+        output.sort();
+        assert_eq!(output, vec!["20", "out"]);
+    }
+
+    #[async_std::test]
+    async fn sink_stops_early() {
+        let mut writer = CollectorWriter::new();
+        let args = PipedArgs {
+            separator: "//".to_string(),
+            stderr: false,
+            pipe_buffer_size: 2,
+            pipefail: PipeFail::Any,
+            summary: false,
+            cmds: CommandArgs::Cmd(vec![
+                "seq".to_owned(),
+                "1000".to_owned(),
+                "//".to_owned(),
+                "head".to_owned(),
+                "-n2".to_owned(),
+            ]),
+        };
+        let res = piped(args, &mut VecReader::new(Vec::<String>::new()), &mut writer).await;
+        assert!(res.is_ok());
+        assert_eq!(*writer.lines().snapshot().await, vec!["1", "2"]);
+    }
+
+    #[async_std::test]
+    async fn endless_source_stopped_when_sink_done() {
+        let mut writer = CollectorWriter::new();
+        let args = PipedArgs {
+            separator: "//".to_string(),
+            stderr: false,
+            pipe_buffer_size: 2,
+            pipefail: PipeFail::Any,
+            summary: false,
+            cmds: CommandArgs::Cmd(vec![
+                "yes".to_owned(),
+                "//".to_owned(),
+                "head".to_owned(),
+                "-n1".to_owned(),
+            ]),
+        };
+        let res = piped(args, &mut VecReader::new(Vec::<String>::new()), &mut writer).await;
+        assert!(res.is_ok());
+        assert_eq!(*writer.lines().snapshot().await, vec!["y"]);
+    }
+
+    #[async_std::test]
+    async fn failing_side_fails() {
+        let args = PipedArgs {
+            separator: "//".to_string(),
+            stderr: false,
+            pipe_buffer_size: 4,
+            pipefail: PipeFail::Any,
+            summary: false,
+            cmds: CommandArgs::Cmd(vec![
+                "false".to_owned(),
+                "//".to_owned(),
+                "cat".to_owned(),
+            ]),
+        };
+        let res = piped(args, &mut VecReader::new(Vec::<String>::new()), &mut CollectorWriter::new()).await;
+        assert!(res.is_err());
+    }
+
+    #[async_std::test]
+    async fn three_stages() {
+        let mut writer = CollectorWriter::new();
+        let args = PipedArgs {
+            separator: "//".to_string(),
+            stderr: false,
+            pipe_buffer_size: 2,
+            pipefail: PipeFail::Any,
+            summary: true,
+            cmds: CommandArgs::Cmd(vec![
+                "seq".to_owned(),
+                "20".to_owned(),
+                "//".to_owned(),
+                "grep".to_owned(),
+                "1".to_owned(),
+                "//".to_owned(),
+                "wc".to_owned(),
+                "-l".to_owned(),
+            ]),
+        };
+        let res = piped(args, &mut VecReader::new(Vec::<String>::new()), &mut writer).await;
+        assert!(res.is_ok());
+        assert_eq!(*writer.lines().snapshot().await, vec!["11"]);
+    }
+
+    #[test]
+    fn empty_stage() {
+        let cmds = CommandArgs::Cmd(vec!["ls".to_owned(), "//".to_owned(), "//".to_owned(), "wc".to_owned()]);
+        assert!(split_tasks(cmds, "//").is_err());
+    }
+
+    #[test]
+    fn pipefail_policies() {
+        let results = [
+            StageResult { status: ExitStatus::of(3), line_count: 0, pipe_closed: false },
+            StageResult { status: ExitStatus::of(5), line_count: 0, pipe_closed: false },
+            StageResult { status: ExitStatus::ok(), line_count: 0, pipe_closed: false },
+        ];
+        assert_eq!(pipeline_status(&results, PipeFail::Any), ExitStatus::of(5));
+        assert_eq!(pipeline_status(&results, PipeFail::First), ExitStatus::of(3));
+        assert_eq!(pipeline_status(&results, PipeFail::Last), ExitStatus::ok());
+        let stopped = [StageResult { status: ExitStatus::of(143), line_count: 9, pipe_closed: true }, results[2]];
+        assert_eq!(pipeline_status(&stopped, PipeFail::Any), ExitStatus::ok());
+    }
 }
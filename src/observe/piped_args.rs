@@ -1,14 +1,15 @@
 use ::clap::Parser;
+use ::clap::ValueEnum;
 
 use crate::common::CommandArgs;
 
 #[derive(Parser, Debug)]
 #[command(
     name = "piped",
-    about = "Split into two commands, and pipe the output of the first into the second."
+    about = "Split into commands, and pipe the output of each into the next, like a shell pipeline."
 )]
 pub struct PipedArgs {
-    /// Which token separates the two commands. Only the first occurrence is matched.
+    /// Which token separates the commands. Can be used multiple times for a pipeline of more than two commands.
     #[arg(short = 's', long = "separator", default_value = "//")]
     pub separator: String,
     /// Pipe stderr instead of stdout into the next command.
@@ -17,14 +18,32 @@ pub struct PipedArgs {
     /// Number of lines to buffer between the commands.
     #[arg(long = "pipe-buffer-size", default_value = "4", value_parser = parse_buffer_size, hide_short_help = true)]
     pub pipe_buffer_size: u32,
+    /// Which command's exit code to use for the pipeline.
+    ///
+    /// [any]: fail with the highest exit code if any command fails
+    /// {n}[first]: exit code of the first command (from the left) that failed
+    /// {n}[last]: exit code of the last command only, like a shell without pipefail
+    #[arg(value_enum, short = 'f', long = "pipefail", default_value = "any")]
+    pub pipefail: PipeFail,
+    /// Print a line for each command with its exit code and number of lines piped onwards.
+    #[arg(short = 'S', long = "summary")]
+    pub summary: bool,
     #[command(subcommand)]
     pub cmds: CommandArgs,
 }
-//TODO @mverleg: 1-to-1, 1-to-many
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeFail {
+    Any,
+    First,
+    Last,
+}
 
 #[test]
 fn test_cli_args() {
     PipedArgs::try_parse_from(&["cmd", "-s=//", "ls", "//", "wc", "-l"]).unwrap();
+    let args = PipedArgs::try_parse_from(&["cmd", "-S", "--pipefail", "last", "ls", "//", "sort", "//", "wc"]).unwrap();
+    assert_eq!(args.pipefail, PipeFail::Last);
 }
 
 fn parse_buffer_size(txt: &str) -> Result<u32, String> {
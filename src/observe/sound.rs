@@ -1,10 +1,8 @@
 use ::std::env;
 
 use ::log::debug;
-use ::log::error;
-use futures::future::join;
-use crate::common::StdWriter;
-use crate::common::Task;
+
+use crate::observe::sound_backend::SoundBackend;
 
 //TODO @mverleg: rename since not just sound anymore?
 pub async fn sound_notification(
@@ -17,48 +15,20 @@ pub async fn sound_notification(
         debug!("sound suppressed by NO_SOUND env var");
         return Ok(())
     }
-    let popup_msg = format!("display notification \"{}\" with title \"{} (mon)\"",
-            details.replace("\"", "").replace("'", "").replace("\\", "\\\\"),
-            if is_success { "OK" } else { "FAILED"});
-    let (sound_task, popup_task) = if is_success && sound_on_success {
+    if is_success && sound_on_success {
         if !env::var("MON_NESTED_SOUND_OK").unwrap_or("".to_owned()).trim().is_empty() {
             debug!("success sound suppressed because of nested `mon` invocations; parent should already play sound");
             return Ok(())
         }
-        (
-            Task::new_in_cwd("say".to_owned(), None, vec!["ready".to_owned()]),
-            Task::new_in_cwd("osascript".to_owned(), None, vec!["-e".to_owned(), popup_msg])
-        )
     } else if !is_success && sound_on_failure {
         if !env::var("MON_NESTED_SOUND_ERR").unwrap_or("".to_owned()).trim().is_empty() {
             debug!("error sound suppressed because of nested `mon` invocations; parent should already play sound");
             return Ok(())
         }
-        (
-            Task::new_in_cwd("say".to_owned(), None, vec!["that failed".to_owned()]),
-            Task::new_in_cwd("osascript".to_owned(), None, vec!["-e".to_owned(), popup_msg])
-        )
     } else {
         return Ok(())
     };
-    //TODO @mverleg: use block_on since async wants recursive future type, and we anyway want to wait
-    let (sound_status, popup_status) = join(
-        sound_task.execute_with_stdout_nomonitor(
-            &mut StdWriter::stdout(),
-            &mut StdWriter::stderr()
-        ),
-        popup_task.execute_with_stdout_nomonitor(
-            &mut StdWriter::stdout(),
-            &mut StdWriter::stderr()
-        ),
-    ).await;
-    if sound_status.is_err() {
-        return Err(format!("failed to play sound using {}", &sound_task.as_cmd_str()))
-    }
-    if popup_status.is_err() {
-        error!("failed to show popup using {}", &popup_task.as_cmd_str())
-    }
-    Ok(())
+    SoundBackend::select()?.notify(is_success, &details).await
 
     // let sl = Soloud::default().unwrap();
     // let mut sound = audio::Wav::default();
@@ -0,0 +1,242 @@
+use ::std::env;
+use ::std::fs;
+use ::std::io::Write;
+use ::std::path::PathBuf;
+use ::std::process;
+
+use ::futures::future::join;
+use ::log::debug;
+use ::log::error;
+use ::which::which;
+
+use crate::common::DiscardWriter;
+use crate::common::StdWriter;
+use crate::common::Task;
+
+pub static BACKEND_ENV_NAME: &str = "MON_NOTIFY_BACKEND";
+
+static SUCCESS_SOUND: &[u8] = include_bytes!("../../resource/mon/success-sound.mp3");
+static ERROR_SOUND: &[u8] = include_bytes!("../../resource/mon/error-sound.mp3");
+
+/// Audio players that can play mp3 files, in order of preference, with their arguments.
+const PLAYERS: [(&str, &[&str]); 6] = [
+    ("mpg123", &["-q"]),
+    ("ffplay", &["-nodisp", "-autoexit", "-loglevel", "quiet"]),
+    ("mpv", &["--no-video", "--really-quiet"]),
+    ("paplay", &[]),
+    ("play", &["-q"]),
+    ("cvlc", &["--play-and-exit", "--quiet"]),
+];
+
+/// How to play a sound and show a popup when `mon` finishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundBackend {
+    /// `say` for speech and `osascript` for a popup.
+    MacOs,
+    /// The bundled sounds through a local audio player, and a freedesktop desktop notification.
+    Linux,
+    /// Only ring the terminal bell.
+    Bell,
+}
+
+impl SoundBackend {
+    /// Backend from env MON_NOTIFY_BACKEND if set, otherwise based on the platform.
+    pub fn select() -> Result<Self, String> {
+        match env::var(BACKEND_ENV_NAME) {
+            Ok(name) if !name.trim().is_empty() => Self::from_name(&name),
+            _ => Ok(Self::for_platform()),
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Self, String> {
+        Ok(match name.trim().to_lowercase().as_str() {
+            "macos" | "mac" | "osx" => SoundBackend::MacOs,
+            "linux" | "freedesktop" => SoundBackend::Linux,
+            "bell" | "terminal" => SoundBackend::Bell,
+            other => return Err(format!(
+                "unknown notification backend '{}' in {}, expected one of: macos, linux, bell",
+                other, BACKEND_ENV_NAME
+            )),
+        })
+    }
+
+    fn for_platform() -> Self {
+        if cfg!(target_os = "macos") {
+            SoundBackend::MacOs
+        } else if cfg!(target_os = "linux") {
+            SoundBackend::Linux
+        } else {
+            SoundBackend::Bell
+        }
+    }
+
+    pub async fn notify(&self, is_success: bool, details: &str) -> Result<(), String> {
+        debug!("notification using {:?} backend (success={})", self, is_success);
+        match self {
+            SoundBackend::MacOs => notify_macos(is_success, details).await,
+            SoundBackend::Linux => notify_linux(is_success, details).await,
+            SoundBackend::Bell => {
+                ring_bell();
+                Ok(())
+            }
+        }
+    }
+}
+
+fn title(is_success: bool) -> String {
+    format!("{} (mon)", if is_success { "OK" } else { "FAILED" })
+}
+
+async fn notify_macos(is_success: bool, details: &str) -> Result<(), String> {
+    let popup_msg = format!("display notification \"{}\" with title \"{}\"",
+            details.replace("\"", "").replace("'", "").replace("\\", "\\\\"),
+            title(is_success));
+    let sound_task = Task::new_in_cwd("say".to_owned(), None, vec![
+        if is_success { "ready" } else { "that failed" }.to_owned()]);
+    let popup_task = Task::new_in_cwd("osascript".to_owned(), None, vec!["-e".to_owned(), popup_msg]);
+    //TODO @mverleg: use block_on since async wants recursive future type, and we anyway want to wait
+    let (sound_status, popup_status) = join(
+        sound_task.execute_with_stdout_nomonitor(
+            &mut StdWriter::stdout(),
+            &mut StdWriter::stderr()
+        ),
+        popup_task.execute_with_stdout_nomonitor(
+            &mut StdWriter::stdout(),
+            &mut StdWriter::stderr()
+        ),
+    ).await;
+    if sound_status.is_err() {
+        return Err(format!("failed to play sound using {}", &sound_task.as_cmd_str()))
+    }
+    if popup_status.is_err() {
+        error!("failed to show popup using {}", &popup_task.as_cmd_str())
+    }
+    Ok(())
+}
+
+async fn notify_linux(is_success: bool, details: &str) -> Result<(), String> {
+    let sound_task = match find_player() {
+        Some((player, player_args)) => {
+            let sound_file = sound_file(is_success)?;
+            let mut args = player_args.iter().map(|arg| (*arg).to_owned()).collect::<Vec<_>>();
+            args.push(sound_file.to_string_lossy().into_owned());
+            Some(Task::new_in_cwd(player, None, args))
+        }
+        None => {
+            debug!("no audio player found (tried {}), using terminal bell",
+                PLAYERS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", "));
+            ring_bell();
+            None
+        }
+    };
+    let popup_task = desktop_notification_task(&title(is_success), details);
+    let (sound_status, popup_status) = join(
+        async {
+            match &sound_task {
+                Some(task) => Some(task.execute_with_stdout_nomonitor(&mut DiscardWriter::new(), &mut StdWriter::stderr()).await),
+                None => None,
+            }
+        },
+        async {
+            match &popup_task {
+                Some(task) => Some(task.execute_with_stdout_nomonitor(&mut DiscardWriter::new(), &mut StdWriter::stderr()).await),
+                None => None,
+            }
+        },
+    ).await;
+    match popup_status {
+        Some(status) if status.is_err() => error!("failed to show desktop notification using {}",
+            popup_task.as_ref().unwrap().as_cmd_str()),
+        Some(_) => {}
+        None => debug!("desktop notification not available"),
+    }
+    if let Some(status) = sound_status {
+        if status.is_err() {
+            return Err(format!("failed to play sound using {}", sound_task.unwrap().as_cmd_str()))
+        }
+    }
+    Ok(())
+}
+
+fn find_player() -> Option<(String, &'static [&'static str])> {
+    PLAYERS.iter()
+        .find_map(|(name, args)| which(name).ok().map(|pth| (pth.to_string_lossy().into_owned(), *args)))
+}
+
+/// The bundled sound is written to the cache directory once, so that players can read it.
+fn sound_file(is_success: bool) -> Result<PathBuf, String> {
+    let mut pth = dirs::cache_dir().ok_or_else(|| "failed to find cache directory".to_owned())?;
+    pth.push("mon_sounds_v1");
+    let (name, data) = if is_success {
+        ("success-sound.mp3", SUCCESS_SOUND)
+    } else {
+        ("error-sound.mp3", ERROR_SOUND)
+    };
+    pth.push(name);
+    if fs::metadata(&pth).is_ok_and(|meta| meta.len() == data.len() as u64) {
+        return Ok(pth);
+    }
+    // write to a temporary file first, so other processes never play a partially written sound
+    let tmp_pth = pth.with_file_name(format!("{}.{}.tmp", name, process::id()));
+    fs::create_dir_all(pth.parent().unwrap())
+        .and_then(|()| fs::write(&tmp_pth, data))
+        .map_err(|err| format!("failed to write notification sound to '{}', error {}", tmp_pth.to_string_lossy(), err))?;
+    fs::rename(&tmp_pth, &pth).map_err(|err| {
+        let _ = fs::remove_file(&tmp_pth);
+        format!("failed to move notification sound into place at '{}', error {}", pth.to_string_lossy(), err)
+    })?;
+    Ok(pth)
+}
+
+/// Desktop notification through the freedesktop D-Bus interface, using gdbus, or notify-send as fallback.
+fn desktop_notification_task(title: &str, body: &str) -> Option<Task> {
+    if env::var_os("DBUS_SESSION_BUS_ADDRESS").is_none() {
+        debug!("no session bus (DBUS_SESSION_BUS_ADDRESS not set), not showing desktop notification");
+        return None;
+    }
+    if which("gdbus").is_ok() {
+        return Some(Task::new_in_cwd("gdbus".to_owned(), None, gdbus_notify_args(title, body)));
+    }
+    if which("notify-send").is_ok() {
+        return Some(Task::new_in_cwd("notify-send".to_owned(), None, vec![
+            "--app-name=mon".to_owned(), title.to_owned(), body.to_owned()]));
+    }
+    None
+}
+
+fn gdbus_notify_args(title: &str, body: &str) -> Vec<String> {
+    [
+        "call", "--session",
+        "--dest", "org.freedesktop.Notifications",
+        "--object-path", "/org/freedesktop/Notifications",
+        "--method", "org.freedesktop.Notifications.Notify",
+        // app name, replaces id, icon, summary, body, actions, hints, timeout ms
+        "mon", "0", "", title, body, "[]", "{}", "5000",
+    ].iter().map(|arg| (*arg).to_owned()).collect()
+}
+
+fn ring_bell() {
+    let mut stderr = ::std::io::stderr();
+    if let Err(err) = stderr.write_all(b"\x07").and_then(|()| stderr.flush()) {
+        debug!("could not ring terminal bell, err {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_names() {
+        assert_eq!(SoundBackend::from_name("Linux"), Ok(SoundBackend::Linux));
+        assert_eq!(SoundBackend::from_name(" bell "), Ok(SoundBackend::Bell));
+        assert_eq!(SoundBackend::from_name("osx"), Ok(SoundBackend::MacOs));
+        assert!(SoundBackend::from_name("speaker").is_err());
+    }
+
+    #[test]
+    fn gdbus_arguments() {
+        let args = gdbus_notify_args("OK (mon)", "took 5 ms");
+        assert_eq!(&args[8..], &["mon", "0", "", "OK (mon)", "took 5 ms", "[]", "{}", "5000"]);
+    }
+}
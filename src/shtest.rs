@@ -0,0 +1,10 @@
+mod handle_shtest;
+mod shtest_args;
+mod shtest_diff;
+mod shtest_parse;
+mod shtest_run;
+mod shtest_shell;
+
+pub use self::handle_shtest::handle_shtest;
+pub use self::shtest_args::ShtestArgs;
+pub use self::shtest_run::shtest;
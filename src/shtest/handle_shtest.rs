@@ -0,0 +1,13 @@
+use crate::shtest::shtest_run::shtest;
+use crate::shtest::ShtestArgs;
+use crate::ExitStatus;
+
+pub fn handle_shtest(args: ShtestArgs) -> ExitStatus {
+    match shtest(args) {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitStatus::err()
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use ::clap::Parser;
+
+use ::rusht::shtest::{handle_shtest, ShtestArgs};
+use ::rusht::ExitStatus;
+
+fn main() -> ExitStatus {
+    env_logger::init_from_env(
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn"),
+    );
+    let args = ShtestArgs::parse();
+    handle_shtest(args)
+}
@@ -0,0 +1,29 @@
+use ::std::path::PathBuf;
+
+use ::clap::Parser;
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    name = "shtest",
+    about = "Run shell test files, checking the output, exit code and duration of each command.",
+    after_help = "Lines in a test file start with a prefix: '$' for a command, followed by 'stdin', 'infile', 'out', 'err', 'code' or 'time' lines that apply to that command. 'first' and 'finally' commands run before and after all steps. Add ':' after a prefix to start an indented multi-line block."
+)]
+pub struct ShtestArgs {
+    /// Test files to run.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    /// Only print failures and the summary, not each passing test.
+    #[arg(short = 'q', long)]
+    pub quiet: bool,
+    /// Stop after the first failing step (cleanup commands still run).
+    #[arg(short = 'x', long)]
+    pub fail_fast: bool,
+    /// Run all commands of a test file in one shell, so that variables and the working directory carry over between steps.
+    #[arg(short = 'p', long)]
+    pub persistent: bool,
+}
+
+#[test]
+fn test_cli_args() {
+    ShtestArgs::try_parse_from(&["cmd", "-q", "--fail-fast", "-p", "a.shtest", "b.shtest"]).unwrap();
+}
@@ -0,0 +1,58 @@
+/// Line-based diff between expected and actual output, using the longest common subsequence.
+/// Lines are prefixed with `-` if only expected, `+` if only actual, or a space if in both.
+pub fn line_diff(expected: &[String], actual: &[String]) -> Vec<String> {
+    let (n, m) = (expected.len(), actual.len());
+    // lcs[i][j] is the common subsequence length of expected[i..] and actual[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut diff = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            diff.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            diff.push(format!("+ {}", actual[j]));
+            j += 1;
+        } else {
+            diff.push(format!("- {}", expected[i]));
+            i += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(txt: &str) -> Vec<String> {
+        txt.split_whitespace().map(|line| line.to_owned()).collect()
+    }
+
+    #[test]
+    fn identical() {
+        assert_eq!(line_diff(&lines("a b"), &lines("a b")), vec!["  a", "  b"]);
+    }
+
+    #[test]
+    fn changed_and_added() {
+        let diff = line_diff(&lines("a b c"), &lines("a x c d"));
+        assert_eq!(diff, vec!["  a", "+ x", "- b", "  c", "+ d"]);
+    }
+
+    #[test]
+    fn empty_sides() {
+        assert_eq!(line_diff(&[], &lines("a")), vec!["+ a"]);
+        assert_eq!(line_diff(&lines("a"), &[]), vec!["- a"]);
+    }
+}
@@ -0,0 +1,292 @@
+use ::std::fmt;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::time::Duration;
+
+use ::log::debug;
+use ::parse_duration0::parse as parse_dur;
+
+/// A shell test file, containing setup and cleanup commands and the steps to test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShTest {
+    pub path: PathBuf,
+    pub first: Vec<String>,
+    pub finally: Vec<String>,
+    pub steps: Vec<Step>,
+}
+
+/// A command and the expectations about its outcome. Expectations that are `None` are not checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub line_nr: usize,
+    pub cmd: String,
+    pub stdin: Option<StdinSource>,
+    pub out: Option<Vec<String>>,
+    pub err: Option<Vec<String>>,
+    pub code: Option<u8>,
+    pub time: Option<TimeCheck>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StdinSource {
+    Literal(Vec<String>),
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeCmp {
+    Less,
+    LessOrEqual,
+    More,
+    MoreOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeCheck {
+    pub cmp: TimeCmp,
+    pub limit: Duration,
+}
+
+impl TimeCheck {
+    pub fn accepts(&self, duration: Duration) -> bool {
+        match self.cmp {
+            TimeCmp::Less => duration < self.limit,
+            TimeCmp::LessOrEqual => duration <= self.limit,
+            TimeCmp::More => duration > self.limit,
+            TimeCmp::MoreOrEqual => duration >= self.limit,
+        }
+    }
+}
+
+impl fmt::Display for TimeCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.cmp {
+            TimeCmp::Less => "<",
+            TimeCmp::LessOrEqual => "<=",
+            TimeCmp::More => ">",
+            TimeCmp::MoreOrEqual => ">=",
+        };
+        write!(f, "{}{} ms", op, self.limit.as_millis())
+    }
+}
+
+/// Parse a shell test file. Lines are interpreted based on their prefix:
+///
+/// * `#` comment
+/// * `$` command to run
+/// * `stdin` literal stdin data for the command
+/// * `infile` file containing stdin data for the command (relative to the test file)
+/// * `out` expected stdout of the command
+/// * `err` expected stderr of the command
+/// * `code` expected exit code of the command (defaults to 0)
+/// * `time` expected duration of the command, e.g. `<2s` or `>=100ms`
+/// * `first` command to run before any steps, output is ignored
+/// * `finally` command to run after all steps, output is ignored
+///
+/// A prefix followed by `:` starts an indented block, for multi-line values.
+/// A bare prefix has no value, so a bare `out` or `err` expects no output.
+pub fn parse_shtest(path: &Path, content: &str) -> Result<ShTest, String> {
+    let mut test = ShTest {
+        path: path.to_owned(),
+        first: vec![],
+        finally: vec![],
+        steps: vec![],
+    };
+    let lines = content.lines().collect::<Vec<_>>();
+    let mut ix = 0;
+    while ix < lines.len() {
+        let line_nr = ix + 1;
+        let line = lines[ix];
+        ix += 1;
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let err_prefix = || format!("{}:{}", path.to_string_lossy(), line_nr);
+        let (keyword, rest) = split_keyword(trimmed);
+        let value = if let Some(after_colon) = rest.strip_prefix(':') {
+            if !after_colon.trim().is_empty() {
+                return Err(format!("{}: block '{}:' should not have content on the same line", err_prefix(), keyword));
+            }
+            let indent = line.len() - trimmed.len();
+            let (block, block_len) = read_block(&lines[ix..], indent);
+            ix += block_len;
+            block
+        } else if rest.is_empty() {
+            vec![]
+        } else if rest.starts_with(' ') || rest.starts_with('\t') {
+            vec![rest[1..].to_owned()]
+        } else {
+            return Err(format!("{}: unknown line prefix in '{}'", err_prefix(), trimmed));
+        };
+        match keyword {
+            "$" => test.steps.push(Step {
+                line_nr,
+                cmd: value.join("\n"),
+                stdin: None,
+                out: None,
+                err: None,
+                code: None,
+                time: None,
+            }),
+            "first" => test.first.push(value.join("\n")),
+            "finally" => test.finally.push(value.join("\n")),
+            "stdin" | "infile" | "out" | "err" | "code" | "time" => {
+                let Some(step) = test.steps.last_mut() else {
+                    return Err(format!("{}: '{}' should follow a '$' command", err_prefix(), keyword));
+                };
+                add_expectation(step, keyword, value, path).map_err(|err| format!("{}: {}", err_prefix(), err))?
+            }
+            "do" => return Err(format!("{}: 'do' instructions are not supported yet", err_prefix())),
+            _ => return Err(format!("{}: unknown line prefix '{}'", err_prefix(), keyword)),
+        }
+    }
+    debug!("parsed {} steps from {}", test.steps.len(), path.to_string_lossy());
+    Ok(test)
+}
+
+fn split_keyword(line: &str) -> (&str, &str) {
+    if let Some(rest) = line.strip_prefix('$') {
+        return ("$", rest);
+    }
+    let end = line.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(line.len());
+    if end == 0 {
+        return (line, "");
+    }
+    line.split_at(end)
+}
+
+/// Lines that are indented more than the block header, dedented. Returns the lines and how many input lines were used.
+fn read_block(lines: &[&str], header_indent: usize) -> (Vec<String>, usize) {
+    let mut block = vec![];
+    for line in lines {
+        let indent = line.len() - line.trim_start().len();
+        if !line.trim().is_empty() && indent <= header_indent {
+            break;
+        }
+        block.push(*line);
+    }
+    let used = block.len();
+    while block.last().is_some_and(|line| line.trim().is_empty()) {
+        block.pop();
+    }
+    let dedent = block.iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let block = block.into_iter()
+        .map(|line| line.get(dedent..).unwrap_or("").to_owned())
+        .collect();
+    (block, used)
+}
+
+fn add_expectation(step: &mut Step, keyword: &str, value: Vec<String>, path: &Path) -> Result<(), String> {
+    match keyword {
+        "stdin" => match &mut step.stdin {
+            Some(StdinSource::Literal(lines)) => lines.extend(value),
+            Some(StdinSource::File(_)) => return Err("cannot use both 'stdin' and 'infile'".to_owned()),
+            None => step.stdin = Some(StdinSource::Literal(value)),
+        },
+        "infile" => {
+            if step.stdin.is_some() {
+                return Err("cannot use 'infile' together with 'stdin' or another 'infile'".to_owned());
+            }
+            let file = PathBuf::from(value.join("\n").trim());
+            let base = path.parent().unwrap_or(Path::new("."));
+            step.stdin = Some(StdinSource::File(base.join(file)));
+        }
+        "out" => step.out.get_or_insert_with(Vec::new).extend(value),
+        "err" => step.err.get_or_insert_with(Vec::new).extend(value),
+        "code" => {
+            if step.code.is_some() {
+                return Err("'code' given more than once".to_owned());
+            }
+            let code_txt = value.join(" ");
+            step.code = Some(code_txt.trim().parse::<u8>()
+                .map_err(|err| format!("could not parse exit code '{}', err {}", code_txt.trim(), err))?);
+        }
+        "time" => {
+            if step.time.is_some() {
+                return Err("'time' given more than once".to_owned());
+            }
+            step.time = Some(parse_time_check(value.join(" ").trim())?);
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn parse_time_check(txt: &str) -> Result<TimeCheck, String> {
+    let (cmp, dur_txt) = if let Some(rest) = txt.strip_prefix("<=") {
+        (TimeCmp::LessOrEqual, rest)
+    } else if let Some(rest) = txt.strip_prefix(">=") {
+        (TimeCmp::MoreOrEqual, rest)
+    } else if let Some(rest) = txt.strip_prefix('<') {
+        (TimeCmp::Less, rest)
+    } else if let Some(rest) = txt.strip_prefix('>') {
+        (TimeCmp::More, rest)
+    } else {
+        return Err(format!("time expression '{}' should start with <, <=, > or >=", txt));
+    };
+    let limit = parse_dur(dur_txt.trim())
+        .map_err(|err| format!("could not parse duration in time expression '{}', err {}", txt, err))?;
+    Ok(TimeCheck { cmp, limit })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<ShTest, String> {
+        parse_shtest(Path::new("/tests/example.shtest"), content)
+    }
+
+    #[test]
+    fn single_line_prefixes() {
+        let test = parse("# comment\nfirst mkdir -p /tmp/x\n$ echo hello\nout hello\ncode 0\ntime <2s\n\n$ cat\nstdin in\nerr\nfinally rm -rf /tmp/x\n").unwrap();
+        assert_eq!(test.first, vec!["mkdir -p /tmp/x"]);
+        assert_eq!(test.finally, vec!["rm -rf /tmp/x"]);
+        assert_eq!(test.steps.len(), 2);
+        assert_eq!(test.steps[0], Step {
+            line_nr: 3,
+            cmd: "echo hello".to_owned(),
+            stdin: None,
+            out: Some(vec!["hello".to_owned()]),
+            err: None,
+            code: Some(0),
+            time: Some(TimeCheck { cmp: TimeCmp::Less, limit: Duration::from_secs(2) }),
+        });
+        assert_eq!(test.steps[1].stdin, Some(StdinSource::Literal(vec!["in".to_owned()])));
+        assert_eq!(test.steps[1].err, Some(vec![]));
+    }
+
+    #[test]
+    fn indented_blocks() {
+        let test = parse("$:\n    echo a\n    echo b\nout:\n  a\n\n    indented\nout c\ninfile input.txt\n").unwrap();
+        assert_eq!(test.steps[0].cmd, "echo a\necho b");
+        assert_eq!(test.steps[0].out, Some(vec!["a".to_owned(), "".to_owned(), "  indented".to_owned(), "c".to_owned()]));
+        assert_eq!(test.steps[0].stdin, Some(StdinSource::File(PathBuf::from("/tests/input.txt"))));
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert!(parse("out hello").unwrap_err().contains("should follow"));
+        assert!(parse("$ ls\nbogus line").unwrap_err().contains("unknown line prefix"));
+        assert!(parse("$ ls\noutput").unwrap_err().contains("unknown line prefix"));
+        assert!(parse("$ ls\ncode abc").is_err());
+        assert!(parse("$ ls\ntime 1s").is_err());
+        assert!(parse("$ ls\nstdin a\ninfile b").is_err());
+        assert!(parse("$ ls\nout: a").is_err());
+    }
+
+    #[test]
+    fn time_checks() {
+        let check = parse_time_check(">= 1s").unwrap();
+        assert!(check.accepts(Duration::from_secs(1)));
+        assert!(!check.accepts(Duration::from_millis(999)));
+        let check = parse_time_check("<100ms").unwrap();
+        assert!(check.accepts(Duration::from_millis(99)));
+        assert!(!check.accepts(Duration::from_millis(100)));
+    }
+}
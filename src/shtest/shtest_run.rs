@@ -0,0 +1,277 @@
+use ::std::env;
+use ::std::fs;
+use ::std::io::Write;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::time::Duration;
+use ::std::time::Instant;
+
+use ::async_std::task::block_on;
+use ::log::debug;
+use ::tempfile::NamedTempFile;
+
+use crate::common::Task;
+use crate::common::VecWriter;
+use crate::shtest::shtest_diff::line_diff;
+use crate::shtest::shtest_parse::parse_shtest;
+use crate::shtest::shtest_parse::ShTest;
+use crate::shtest::shtest_parse::StdinSource;
+use crate::shtest::shtest_parse::Step;
+use crate::shtest::shtest_shell::PersistentShell;
+use crate::shtest::ShtestArgs;
+use crate::ExitStatus;
+
+/// What happened when running a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepOutcome {
+    pub out: Vec<String>,
+    pub err: Vec<String>,
+    pub code: u8,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TestCounts {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+pub fn shtest(args: ShtestArgs) -> Result<ExitStatus, String> {
+    let mut counts = TestCounts::default();
+    for path in &args.files {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read test file '{}', error {}", path.to_string_lossy(), err))?;
+        let test = parse_shtest(path, &content)?;
+        let file_counts = run_test_file(&test, args.quiet, args.fail_fast, args.persistent);
+        counts.passed += file_counts.passed;
+        counts.failed += file_counts.failed;
+        if args.fail_fast && file_counts.failed > 0 {
+            break;
+        }
+    }
+    println!("{} passed, {} failed", counts.passed, counts.failed);
+    Ok(ExitStatus::of_is_ok(counts.failed == 0))
+}
+
+/// Runs the setup commands, all the steps and then the cleanup commands of one test file.
+/// If `persistent`, all of these run in the same shell, otherwise each gets a new shell.
+pub fn run_test_file(test: &ShTest, quiet: bool, fail_fast: bool, persistent: bool) -> TestCounts {
+    let mut counts = TestCounts::default();
+    let dir = test_dir(&test.path);
+    let name = test.path.to_string_lossy();
+    let mut shell = if persistent {
+        Shell::Persistent(PersistentShell::new(dir))
+    } else {
+        Shell::Fresh(dir)
+    };
+    match run_setup(&mut shell, &test.first) {
+        Ok(()) => {
+            for step in &test.steps {
+                let failures = match shell.run(&step.cmd, step.stdin.as_ref()) {
+                    Ok(outcome) => check_step(step, &outcome),
+                    Err(err) => vec![err],
+                };
+                if failures.is_empty() {
+                    counts.passed += 1;
+                    if !quiet {
+                        println!("ok   {}:{} $ {}", name, step.line_nr, first_line(&step.cmd));
+                    }
+                    continue;
+                }
+                counts.failed += 1;
+                println!("FAIL {}:{} $ {}", name, step.line_nr, first_line(&step.cmd));
+                for failure in failures {
+                    println!("{}", failure);
+                }
+                if fail_fast {
+                    break;
+                }
+            }
+        }
+        Err(err) => {
+            counts.failed += 1;
+            println!("FAIL {} setup: {}", name, err);
+        }
+    }
+    for cmd in &test.finally {
+        if let Err(err) = run_ignore_output(&mut shell, cmd) {
+            eprintln!("cleanup of {} failed: {}", name, err);
+        }
+    }
+    counts
+}
+
+fn test_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => env::current_dir().expect("could not determine working directory"),
+    }
+}
+
+fn first_line(cmd: &str) -> &str {
+    cmd.lines().next().unwrap_or("")
+}
+
+fn run_setup(shell: &mut Shell, cmds: &[String]) -> Result<(), String> {
+    for cmd in cmds {
+        run_ignore_output(shell, cmd)?;
+    }
+    Ok(())
+}
+
+fn run_ignore_output(shell: &mut Shell, cmd: &str) -> Result<(), String> {
+    let outcome = shell.run(cmd, None)?;
+    if outcome.code != 0 {
+        return Err(format!("command '{}' failed with code {}", first_line(cmd), outcome.code));
+    }
+    Ok(())
+}
+
+/// Runs each command in a new shell, or all of them in the same one.
+#[derive(Debug)]
+enum Shell {
+    Fresh(PathBuf),
+    Persistent(PersistentShell),
+}
+
+impl Shell {
+    fn run(&mut self, cmd: &str, stdin: Option<&StdinSource>) -> Result<StepOutcome, String> {
+        match self {
+            Shell::Fresh(dir) => {
+                let stdin = match stdin {
+                    None => None,
+                    Some(StdinSource::Literal(lines)) => Some(lines_to_text(lines)),
+                    Some(StdinSource::File(pth)) => Some(fs::read_to_string(pth)
+                        .map_err(|err| format!("failed to read stdin file '{}', error {}", pth.to_string_lossy(), err))?),
+                };
+                Ok(run_shell(dir, cmd, stdin))
+            }
+            Shell::Persistent(shell) => match stdin {
+                None => shell.run(cmd, None),
+                Some(StdinSource::Literal(lines)) => {
+                    let mut file = NamedTempFile::new()
+                        .map_err(|err| format!("failed to create stdin file, error {}", err))?;
+                    file.write_all(lines_to_text(lines).as_bytes())
+                        .map_err(|err| format!("failed to write stdin file, error {}", err))?;
+                    shell.run(cmd, Some(file.path()))
+                }
+                Some(StdinSource::File(pth)) => {
+                    // the shell may have changed directory, so the path should be absolute
+                    let cwd = env::current_dir().map_err(|err| format!("could not determine working directory, error {}", err))?;
+                    shell.run(cmd, Some(&cwd.join(pth)))
+                }
+            },
+        }
+    }
+}
+
+fn lines_to_text(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// Runs the command in a new shell and collects its output.
+fn run_shell(dir: &Path, cmd: &str, stdin: Option<String>) -> StepOutcome {
+    let task = Task::new("sh".to_owned(), vec!["-c".to_owned(), cmd.to_owned()], dir.to_owned(), stdin);
+    let mut out = VecWriter::new();
+    let mut err = VecWriter::new();
+    let t0 = Instant::now();
+    let status = block_on(task.execute_with_outerr(false, &mut out, &mut err));
+    let duration = t0.elapsed();
+    debug!("ran '{}' in {} ms with code {}", first_line(cmd), duration.as_millis(), status.code());
+    StepOutcome {
+        out: out.get(),
+        err: err.get(),
+        code: status.code(),
+        duration,
+    }
+}
+
+/// Compares the outcome to the expectations of the step, returning a description of each mismatch.
+pub fn check_step(step: &Step, outcome: &StepOutcome) -> Vec<String> {
+    let mut failures = vec![];
+    let expected_code = step.code.unwrap_or(0);
+    if outcome.code != expected_code {
+        failures.push(format!("  exit code: expected {}, got {}", expected_code, outcome.code));
+    }
+    if let Some(expected) = &step.out {
+        if expected != &outcome.out {
+            failures.push(format_diff("stdout", expected, &outcome.out));
+        }
+    }
+    if let Some(expected) = &step.err {
+        if expected != &outcome.err {
+            failures.push(format_diff("stderr", expected, &outcome.err));
+        }
+    }
+    if let Some(time) = &step.time {
+        if !time.accepts(outcome.duration) {
+            failures.push(format!("  duration: expected {}, took {} ms", time, outcome.duration.as_millis()));
+        }
+    }
+    failures
+}
+
+fn format_diff(name: &str, expected: &[String], actual: &[String]) -> String {
+    let mut text = format!("  {} differs (- expected, + actual):", name);
+    for line in line_diff(expected, actual) {
+        text.push_str("\n    ");
+        text.push_str(&line);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(content: &str) -> TestCounts {
+        let test = parse_shtest(&env::temp_dir().join("rusht_shtest_example.shtest"), content).unwrap();
+        run_test_file(&test, true, false, false)
+    }
+
+    fn run_persistent(content: &str) -> TestCounts {
+        let test = parse_shtest(&env::temp_dir().join("rusht_shtest_example.shtest"), content).unwrap();
+        run_test_file(&test, true, false, true)
+    }
+
+    #[test]
+    fn passing_steps() {
+        let counts = run("$ echo hello\nout hello\n$ echo oops >&2; exit 3\nerr oops\ncode 3\n$ cat\nstdin:\n  a\n  b\nout:\n  a\n  b\ntime <10s\n");
+        assert_eq!(counts, TestCounts { passed: 3, failed: 0 });
+    }
+
+    #[test]
+    fn failing_steps() {
+        let counts = run("$ echo hello\nout bye\n$ exit 1\n$ true\ntime >1h\n$ true\n");
+        assert_eq!(counts, TestCounts { passed: 1, failed: 3 });
+    }
+
+    #[test]
+    fn failed_setup_skips_steps() {
+        let counts = run("first exit 1\n$ true\n");
+        assert_eq!(counts, TestCounts { passed: 0, failed: 1 });
+    }
+
+    #[test]
+    fn persistent_variables() {
+        let content = "first export BASE=x\n$ FOO=1\n$ echo $BASE$FOO\nout x1\n$ read line; echo \"got $line\"\nstdin hi\nout got hi\n";
+        assert_eq!(run_persistent(content), TestCounts { passed: 3, failed: 0 });
+        assert_eq!(run(content), TestCounts { passed: 2, failed: 1 });
+    }
+
+    #[test]
+    fn mismatch_descriptions() {
+        let test = parse_shtest(Path::new("x.shtest"), "$ true\nout a\ncode 2\n").unwrap();
+        let outcome = StepOutcome {
+            out: vec!["b".to_owned()],
+            err: vec![],
+            code: 0,
+            duration: Duration::from_millis(1),
+        };
+        let failures = check_step(&test.steps[0], &outcome);
+        assert_eq!(failures, vec![
+            "  exit code: expected 2, got 0".to_owned(),
+            "  stdout differs (- expected, + actual):\n    + b\n    - a".to_owned(),
+        ]);
+    }
+}
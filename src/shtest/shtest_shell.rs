@@ -0,0 +1,233 @@
+use ::std::io::BufRead;
+use ::std::io::BufReader;
+use ::std::io::Read;
+use ::std::io::Write;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::process::Child;
+use ::std::process::ChildStdin;
+use ::std::process::Command;
+use ::std::process::Stdio;
+use ::std::sync::mpsc::channel;
+use ::std::sync::mpsc::Receiver;
+use ::std::thread;
+use ::std::thread::JoinHandle;
+use ::std::time::Instant;
+
+use ::log::debug;
+use ::rand::random;
+
+use crate::shtest::shtest_run::StepOutcome;
+
+/// A single shell process that runs commands one after the other, so that variables,
+/// functions and the working directory carry over between commands.
+///
+/// After each command, a marker line with the exit code is printed to stdout and another
+/// marker to stderr, which is used to split the output of different commands.
+/// If a command makes the shell exit, a new shell is started for the next command.
+#[derive(Debug)]
+pub struct PersistentShell {
+    dir: PathBuf,
+    marker: String,
+    proc: Option<ShellProcess>,
+}
+
+#[derive(Debug)]
+struct ShellProcess {
+    child: Child,
+    stdin: ChildStdin,
+    out: Receiver<String>,
+    err: Receiver<String>,
+    readers: [JoinHandle<()>; 2],
+}
+
+impl PersistentShell {
+    pub fn new(dir: PathBuf) -> Self {
+        PersistentShell {
+            dir,
+            marker: format!("__SHTEST_DONE_{:016x}__", random::<u64>()),
+            proc: None,
+        }
+    }
+
+    /// Run the command in the shell, reading stdin from the given file (or nothing).
+    pub fn run(&mut self, cmd: &str, stdin_file: Option<&Path>) -> Result<StepOutcome, String> {
+        // incomplete syntax (like an unterminated quote) would make the shell wait for more input forever
+        check_syntax(&self.dir, cmd)?;
+        if self.proc.is_none() {
+            self.proc = Some(ShellProcess::start(&self.dir)?);
+        }
+        let proc = self.proc.as_mut().unwrap();
+        let stdin_path = stdin_file
+            .map(|pth| pth.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "/dev/null".to_owned());
+        // the command gets its own stdin, so that it cannot read the script for the next commands
+        let script = format!(
+            "{{\n{}\n}} < {}\n__shtest_code=$?\nprintf '%s %s\\n' '{}' \"$__shtest_code\"\nprintf '%s\\n' '{}' >&2\n",
+            cmd,
+            shell_quote(&stdin_path),
+            self.marker,
+            self.marker,
+        );
+        let t0 = Instant::now();
+        if let Err(err) = proc.stdin.write_all(script.as_bytes()).and_then(|()| proc.stdin.flush()) {
+            debug!("could not send command to persistent shell, it probably exited, err {}", err);
+        }
+        let (out, code) = collect_until_marker(&proc.out, &self.marker);
+        let (err, _) = collect_until_marker(&proc.err, &self.marker);
+        let duration = t0.elapsed();
+        let code = match code {
+            Some(code) => code,
+            None => {
+                let proc = self.proc.take().unwrap();
+                let code = proc.finish();
+                debug!("persistent shell exited with code {} while running '{}'; a new shell will be started", code, cmd);
+                code
+            }
+        };
+        Ok(StepOutcome { out, err, code, duration })
+    }
+}
+
+impl Drop for PersistentShell {
+    fn drop(&mut self) {
+        if let Some(proc) = self.proc.take() {
+            let code = proc.finish();
+            debug!("persistent shell stopped with code {}", code);
+        }
+    }
+}
+
+impl ShellProcess {
+    fn start(dir: &Path) -> Result<Self, String> {
+        let mut child = Command::new("sh")
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("failed to start persistent shell, error {}", err))?;
+        let stdin = child.stdin.take().unwrap();
+        let (out, out_reader) = forward_lines(child.stdout.take().unwrap());
+        let (err, err_reader) = forward_lines(child.stderr.take().unwrap());
+        debug!("started persistent shell with pid {} in {}", child.id(), dir.to_string_lossy());
+        Ok(ShellProcess {
+            child,
+            stdin,
+            out,
+            err,
+            readers: [out_reader, err_reader],
+        })
+    }
+
+    /// Close the shell's input and wait for it to exit, returning the exit code.
+    fn finish(self) -> u8 {
+        let ShellProcess { mut child, stdin, readers, .. } = self;
+        drop(stdin);
+        let code = match child.wait() {
+            Ok(status) => status.code().map(|code| code as u8).unwrap_or(1),
+            Err(err) => {
+                debug!("failed to wait for persistent shell, err {}", err);
+                1
+            }
+        };
+        for reader in readers {
+            reader.join().expect("thread panic");
+        }
+        code
+    }
+}
+
+/// Parse the command without running it, failing if it is not complete.
+fn check_syntax(dir: &Path, cmd: &str) -> Result<(), String> {
+    let output = Command::new("sh")
+        .args(["-n", "-c", &format!("{{\n{}\n}}", cmd)])
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| format!("failed to check shell syntax, error {}", err))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(format!("invalid shell syntax: {}", String::from_utf8_lossy(&output.stderr).trim()))
+}
+
+/// Reads lines in a separate thread, so that stdout and stderr cannot block each other.
+fn forward_lines(stream: impl Read + Send + 'static) -> (Receiver<String>, JoinHandle<()>) {
+    let (sender, receiver) = channel();
+    let handle = thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    (receiver, handle)
+}
+
+/// Collect lines until the marker. Returns the exit code after the marker if present,
+/// or `None` if the stream ended first. Output without trailing newline ends up on the marker line.
+fn collect_until_marker(lines: &Receiver<String>, marker: &str) -> (Vec<String>, Option<u8>) {
+    let mut collected = vec![];
+    while let Ok(line) = lines.recv() {
+        if let Some(pos) = line.find(marker) {
+            if pos > 0 {
+                collected.push(line[..pos].to_owned());
+            }
+            let code = line[pos + marker.len()..].trim().parse::<u8>().unwrap_or(0);
+            return (collected, Some(code));
+        }
+        collected.push(line);
+    }
+    (collected, None)
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::env;
+
+    use super::*;
+
+    #[test]
+    fn state_carries_over() {
+        let mut shell = PersistentShell::new(env::temp_dir());
+        let first = shell.run("FOO=hello; cd /; echo set", None).unwrap();
+        assert_eq!(first.out, vec!["set"]);
+        let second = shell.run("echo $FOO; pwd; echo warn >&2; printf partial; false", None).unwrap();
+        assert_eq!(second.out, vec!["hello", "/", "partial"]);
+        assert_eq!(second.err, vec!["warn"]);
+        assert_eq!(second.code, 1);
+    }
+
+    #[test]
+    fn restarts_after_exit() {
+        let mut shell = PersistentShell::new(env::temp_dir());
+        shell.run("FOO=1", None).unwrap();
+        let exited = shell.run("echo bye; exit 4", None).unwrap();
+        assert_eq!(exited.out, vec!["bye"]);
+        assert_eq!(exited.code, 4);
+        let restarted = shell.run("echo \"[$FOO]\"", None).unwrap();
+        assert_eq!(restarted.out, vec!["[]"]);
+        assert_eq!(restarted.code, 0);
+    }
+
+    #[test]
+    fn incomplete_syntax_fails() {
+        let mut shell = PersistentShell::new(env::temp_dir());
+        assert!(shell.run("echo 'unterminated", None).is_err());
+        assert!(shell.run("if true; then echo a", None).is_err());
+        assert_eq!(shell.run("echo ok", None).unwrap().out, vec!["ok"]);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
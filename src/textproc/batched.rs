@@ -5,7 +5,7 @@ use std::cmp::max;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
-use crate::common::{LineReader, LineWriter, StdWriter, Task};
+use crate::common::{skip_for_dry_run, LineReader, LineWriter, StdWriter, Task};
 use crate::textproc::batched_args::BatchedArgs;
 
 pub async fn batched(
@@ -59,6 +59,7 @@ async fn batched_unfiltered(
             debug!("handling batch #{} of size {}", batch_nr, batch.len());
             batch_nr += 1;
             run_batch(&batch, &task, writer).await?;
+            batch.clear();
         }
         batch.push(line.to_owned());
         // ^ can reuse this string allocation but not worth it at all
@@ -279,10 +280,18 @@ fn batched_apart(
     batches
 }
 
-async fn run_batch(_batch: &[String], task: &Task, writer: &mut impl LineWriter) -> Result<(), String> {
-    let _res = task.execute_with_stdout_nomonitor(writer, &mut StdWriter::stderr()).await;
-    todo!("waiting for exec2 code");
-    //Ok(())
+async fn run_batch(batch: &[String], task: &Task, writer: &mut impl LineWriter) -> Result<(), String> {
+    let mut batch_task = task.clone();
+    // end every line with a newline, like when streaming input, so line-based commands see the last line
+    batch_task.stdin = Some(batch.iter().map(|line| format!("{}\n", line)).collect());
+    if skip_for_dry_run(&batch_task, false).is_some() {
+        return Ok(())
+    }
+    let status = batch_task.execute_with_stdout_nomonitor(writer, &mut StdWriter::stderr()).await;
+    if status.is_err() {
+        return Err(format!("batch of {} lines failed with code {}: {}", batch.len(), status.code(), task.as_short_cmd_str()));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -290,7 +299,6 @@ mod tests {
     use super::*;
     use crate::common::{CollectorWriter, CommandArgs, VecReader};
 
-    #[ignore]  //TODO @mverleg: enable when `batched` is ready
     #[async_std::test]
     async fn batch_2_wcl() {
         let mut writer = CollectorWriter::new();
@@ -303,9 +311,10 @@ mod tests {
             apart: None,
             mixed_groups: false,
             drop_unmatched: false,
+            dry_run: false,
         };
         let res = batched(args, &mut VecReader::new(inp), &mut writer).await;
-        assert!(res.is_err());
+        assert!(res.is_ok());
         assert_eq!(
             *out_lines.snapshot().await,
             vec!["2".to_owned(), "2".to_owned(), "1".to_owned()]
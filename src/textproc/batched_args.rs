@@ -24,6 +24,9 @@ pub struct BatchedArgs {
     /// Drop any items that do not match the regex, they are not included in any batch.
     #[arg(long)]
     pub drop_unmatched: bool,
+    #[arg(long)]
+    /// Only show the commands that would run, and why, without running them. Can also set RUSHT_DRY_RUN.
+    pub dry_run: bool,
     #[command(subcommand)]
     pub cmd: CommandArgs,
 }
@@ -41,4 +44,5 @@ fn test_cli_args() {
     ])
     .unwrap();
     BatchedArgs::try_parse_from(&["batched", "-c=2", "--together", "^\\w+", "implode"]).unwrap();
+    BatchedArgs::try_parse_from(&["batched", "-c=3", "--dry-run", "wc", "-l"]).unwrap();
 }
@@ -1,9 +1,12 @@
-use crate::common::{StdWriter, StdinReader};
+use crate::common::{enable_dry_run, StdWriter, StdinReader};
 use crate::textproc::batched::batched;
 use crate::textproc::batched_args::BatchedArgs;
 use crate::ExitStatus;
 
 pub async fn handle_batched(args: BatchedArgs) -> ExitStatus {
+    if args.dry_run {
+        enable_dry_run();
+    }
     match batched(args, &mut StdinReader::new(), &mut StdWriter::stdout()).await {
         Ok(()) => ExitStatus::ok(),
         Err(err) => {
@@ -1,9 +1,14 @@
 pub use self::handle::handle_locked;
+pub use self::handle::handle_wait_for;
 pub use self::locked::locked;
 pub use self::locked_args::LockedArgs;
+pub use self::portwait::wait_for;
+pub use self::wait_for_args::WaitForArgs;
 
 mod handle;
 mod locked;
 mod locked_args;
 mod locked_lock;
+mod locked_show;
 mod portwait;
+mod wait_for_args;
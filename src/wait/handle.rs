@@ -1,16 +1,26 @@
 use crate::ExitStatus;
 
+use super::locked;
+use super::wait_for;
 use super::LockedArgs;
+use super::WaitForArgs;
 
-pub fn handle_locked(_args: LockedArgs) -> ExitStatus {
-    unimplemented!("does not seem to work?");
-    // seq 10 | cmbuf -p8 sh -c 'locked -f=x sleep 1; echo {}'
+pub fn handle_locked(args: LockedArgs) -> ExitStatus {
+    match locked(args) {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("failed: {}", err);
+            ExitStatus::err()
+        }
+    }
+}
 
-    // match locked(_args) {
-    //     Ok(()) => ExitStatus::ok(),
-    //     Err(err) => {
-    //         eprintln!("failed: {}", err);
-    //         ExitStatus::err()
-    //     }
-    // }
+pub fn handle_wait_for(args: WaitForArgs) -> ExitStatus {
+    match wait_for(args) {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("failed: {}", err);
+            ExitStatus::err()
+        }
+    }
 }
@@ -1,20 +1,183 @@
+use ::std::cmp::min;
+use ::std::env;
+use ::std::path::PathBuf;
+use ::std::sync::mpsc::channel;
+use ::std::sync::mpsc::RecvTimeoutError;
+use ::std::sync::mpsc::Sender;
+use ::std::thread;
+use ::std::thread::sleep;
+use ::std::thread::JoinHandle;
+use ::std::time::Duration;
+use ::std::time::Instant;
+
+use ::log::debug;
+use ::log::warn;
+
+use crate::common::Task;
+use crate::wait::locked_lock::lock_path;
+use crate::wait::locked_lock::remove_lock_file;
+use crate::wait::locked_lock::update_lock_file;
+use crate::wait::locked_lock::LockHolder;
+use crate::wait::locked_lock::LEASE_DURATION;
+use crate::wait::locked_show::show_all_locks;
 use crate::wait::LockedArgs;
+use crate::ExitStatus;
+
+const MIN_CHECK_INTERVAL: Duration = Duration::from_millis(20);
+const MAX_CHECK_INTERVAL: Duration = Duration::from_millis(1000);
 
-#[allow(unreachable_code)] //TODO @mverleg: TEMPORARY! REMOVE THIS!
-pub fn locked(args: LockedArgs) -> Result<(), String> {
+pub fn locked(args: LockedArgs) -> Result<ExitStatus, String> {
     if args.show {
-        unimplemented!();
-        return Ok(());
+        for line in show_all_locks(args.json)? {
+            println!("{}", line);
+        }
+        return Ok(ExitStatus::ok());
     }
+    let task = args.cmd.map(|cmd| cmd.into_task());
+    let key = expand_key(&args.lock_key, task.as_ref())?;
+    let pth = lock_path(&key)?;
     if args.unlock {
-        unimplemented!();
-        return Ok(());
+        remove_lock_file(&pth)?;
+        eprintln!("removed lock for '{}'", key);
+        return Ok(ExitStatus::ok());
+    }
+    let Some(task) = task else {
+        return Err("no command given to run while holding the lock".to_owned());
+    };
+    let _lock = HeldLock::acquire(key, pth, task.clone(), args.read, args.timeout, args.show_progress)?;
+    Ok(task.execute_sync(true))
+}
+
+fn expand_key(key_template: &str, task: Option<&Task>) -> Result<String, String> {
+    let mut key = key_template.to_owned();
+    if key.contains("%{pwd}") {
+        let cwd = env::current_dir()
+            .map_err(|err| format!("could not determine working directory for lock key, err {err}"))?;
+        key = key.replace("%{pwd}", &cwd.to_string_lossy());
+    }
+    if key.contains("%{cmd}") {
+        let Some(task) = task else {
+            return Err("lock key contains %{cmd} but no command was given".to_owned());
+        };
+        key = key.replace("%{cmd}", &task.as_cmd_str());
+    }
+    debug!("lock key '{}' expanded to '{}'", key_template, key);
+    Ok(key)
+}
+
+/// Holds a reader or writer lock until dropped, refreshing the heartbeat in the background.
+#[derive(Debug)]
+struct HeldLock {
+    key: String,
+    pth: PathBuf,
+    holder: LockHolder,
+    heartbeat: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl HeldLock {
+    fn acquire(
+        key: String,
+        pth: PathBuf,
+        task: Task,
+        read: bool,
+        timeout: Duration,
+        show_progress: bool,
+    ) -> Result<Self, String> {
+        let holder = LockHolder::current(task);
+        let t0 = Instant::now();
+        let mut interval = MIN_CHECK_INTERVAL;
+        let mut prev_holders = String::new();
+        loop {
+            let (is_acquired, holders, evicted) = update_lock_file(&pth, &key, |lock| {
+                let evicted = lock.evict_stale(LockHolder::find_stale_reason);
+                (lock.try_acquire(&holder, read), lock.describe_holders(), evicted)
+            })?;
+            for (stale, reason) in evicted {
+                if show_progress {
+                    eprintln!("evicted stale lock holder {} ({})", stale.describe(), reason);
+                } else {
+                    debug!("evicted stale lock holder {} ({})", stale.describe(), reason);
+                }
+            }
+            if is_acquired {
+                debug!(
+                    "acquired {} lock '{}' after {} ms",
+                    if read { "read" } else { "write" },
+                    key,
+                    t0.elapsed().as_millis()
+                );
+                let heartbeat = Some(start_heartbeat(key.clone(), pth.clone(), holder.clone()));
+                return Ok(HeldLock { key, pth, holder, heartbeat });
+            }
+            if t0.elapsed() >= timeout {
+                update_lock_file(&pth, &key, |lock| lock.release(&holder))?;
+                return Err(format!(
+                    "timed out after {}s waiting for lock '{}', held by {}",
+                    t0.elapsed().as_secs(),
+                    key,
+                    holders
+                ));
+            }
+            if show_progress && holders != prev_holders {
+                eprintln!(
+                    "waiting for lock '{}' held by {} (checking every {} ms, waited {}s)",
+                    key,
+                    holders,
+                    interval.as_millis(),
+                    t0.elapsed().as_secs()
+                );
+                prev_holders = holders;
+            }
+            sleep(min(interval, timeout.saturating_sub(t0.elapsed())));
+            interval = min(interval * 2, MAX_CHECK_INTERVAL);
+        }
+    }
+}
+
+fn start_heartbeat(key: String, pth: PathBuf, holder: LockHolder) -> (Sender<()>, JoinHandle<()>) {
+    let (stop_sender, stop_receiver) = channel::<()>();
+    let handle = thread::spawn(move || {
+        // stops when the sender is dropped
+        while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(LEASE_DURATION / 4) {
+            match update_lock_file(&pth, &key, |lock| lock.refresh(&holder)) {
+                Ok(true) => debug!("refreshed heartbeat for lock '{}'", key),
+                Ok(false) => warn!("lock '{}' was taken away while still running", key),
+                Err(err) => warn!("failed to refresh heartbeat for lock '{}': {}", key, err),
+            }
+        }
+    });
+    (stop_sender, handle)
+}
+
+impl Drop for HeldLock {
+    fn drop(&mut self) {
+        if let Some((stop_sender, handle)) = self.heartbeat.take() {
+            drop(stop_sender);
+            handle.join().expect("heartbeat thread panicked");
+        }
+        match update_lock_file(&self.pth, &self.key, |lock| lock.release(&self.holder)) {
+            Ok(true) => debug!("released lock '{}'", self.key),
+            Ok(false) => warn!("lock '{}' was no longer held when releasing; was it unlocked?", self.key),
+            Err(err) => eprintln!("failed to release lock '{}': {}", self.key, err),
+        }
     }
-    let task = args.cmd.into_task();
-    let status = task.execute_sync(true);
-    if status.is_err() {
-        return Err(format!("failed with status {}", status.code()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_placeholders() {
+        let task = Task::new_split_in_cwd(vec!["ls".to_owned(), "-a".to_owned()]);
+        let key = expand_key("%{pwd}:%{cmd}.lock", Some(&task)).unwrap();
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(key, format!("{}:{}.lock", cwd.to_string_lossy(), task.as_cmd_str()));
+    }
+
+    #[test]
+    fn expand_cmd_without_task() {
+        assert_eq!(expand_key("x", None), Ok("x".to_owned()));
+        assert!(expand_key("%{cmd}", None).is_err());
     }
-    //TODO @mverleg:
-    Ok(())
 }
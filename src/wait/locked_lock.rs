@@ -1,21 +1,525 @@
-#![allow(unused)]  //TODO @mverleg: TEMPORARY! REMOVE THIS!
+use ::std::fs;
+use ::std::fs::create_dir_all;
+use ::std::fs::OpenOptions;
+use ::std::io;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::process;
+use ::std::thread::sleep;
+use ::std::time::Duration;
+use ::std::time::Instant;
+use ::std::time::SystemTime;
 
+use ::itertools::Itertools;
+use ::log::debug;
+use ::log::warn;
 use ::serde::Deserialize;
 use ::serde::Serialize;
 use ::time::OffsetDateTime;
 
+use crate::common::is_process_alive;
+use crate::common::process_start_time;
+use crate::common::unique_filename;
 use crate::common::Task;
 
+/// Increment for breaking changes, to avoid loading old lock files
+pub const DATA_VERSION: u32 = 2;
+
+/// Guard files older than this are assumed to be left behind by a crashed process.
+const GUARD_ABANDONED_AFTER: Duration = Duration::from_secs(10);
+
+/// Holders that have not refreshed their heartbeat for this long are considered dead.
+pub const LEASE_DURATION: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-struct LockFile {
-    key: String,
-    writer: Option<LockHolder>,
-    readers: Vec<LockHolder>,
+pub struct LockFile {
+    pub key: String,
+    pub writer: Option<LockHolder>,
+    pub readers: Vec<LockHolder>,
+    /// Processes that want the lock; for these `acquired` is when they started waiting.
+    #[serde(default)]
+    pub waiting: Vec<LockHolder>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-struct LockHolder {
-    pid: u8,
-    acquired: OffsetDateTime,
-    task: Task,
+pub struct LockHolder {
+    pub pid: u32,
+    pub pid_start: Option<String>,
+    pub acquired: OffsetDateTime,
+    pub heartbeat: OffsetDateTime,
+    pub task: Task,
+}
+
+impl LockFile {
+    pub fn new(key: String) -> Self {
+        LockFile {
+            key,
+            writer: None,
+            readers: vec![],
+            waiting: vec![],
+        }
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.writer.is_none() && self.readers.is_empty()
+    }
+
+    /// No holders and nobody waiting, so the lock file can be removed.
+    pub fn is_unused(&self) -> bool {
+        self.is_free() && self.waiting.is_empty()
+    }
+
+    /// Readers can share the lock with other readers, writers need it exclusively.
+    /// If the lock cannot be acquired, the holder is registered as waiting.
+    pub fn try_acquire(&mut self, holder: &LockHolder, read: bool) -> bool {
+        let can_acquire = self.writer.is_none() && (read || self.readers.is_empty());
+        if !can_acquire {
+            self.wait(holder);
+            return false;
+        }
+        self.waiting.retain(|waiter| !waiter.is_same(holder));
+        let mut holder = holder.clone();
+        holder.acquired = OffsetDateTime::now_utc();
+        holder.heartbeat = holder.acquired;
+        if read {
+            self.readers.push(holder);
+        } else {
+            self.writer = Some(holder);
+        }
+        true
+    }
+
+    fn wait(&mut self, holder: &LockHolder) {
+        if !self.refresh(holder) {
+            self.waiting.push(holder.clone());
+        }
+    }
+
+    /// Remove the holder, whether it holds the lock or is waiting for it.
+    pub fn release(&mut self, holder: &LockHolder) -> bool {
+        let count = self.all().count();
+        if self.writer.as_ref().is_some_and(|writer| writer.is_same(holder)) {
+            self.writer = None;
+        }
+        self.readers.retain(|reader| !reader.is_same(holder));
+        self.waiting.retain(|waiter| !waiter.is_same(holder));
+        count != self.all().count()
+    }
+
+    /// Update the heartbeat of the holder, returning false if it is not found.
+    pub fn refresh(&mut self, holder: &LockHolder) -> bool {
+        let now = OffsetDateTime::now_utc();
+        let mut found = false;
+        for current in self.writer.iter_mut().chain(self.readers.iter_mut()).chain(self.waiting.iter_mut()) {
+            if current.is_same(holder) {
+                current.heartbeat = now;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Holders followed by waiting processes.
+    pub fn all(&self) -> impl Iterator<Item = &LockHolder> {
+        self.writer.iter().chain(self.readers.iter()).chain(self.waiting.iter())
+    }
+
+    /// Remove holders for which `find_stale` gives a reason, returning them together with that reason.
+    pub fn evict_stale(
+        &mut self,
+        find_stale: impl Fn(&LockHolder) -> Option<String>,
+    ) -> Vec<(LockHolder, String)> {
+        let mut evicted = vec![];
+        if let Some(writer) = &self.writer {
+            if let Some(reason) = find_stale(writer) {
+                evicted.push((self.writer.take().unwrap(), reason));
+            }
+        }
+        for holders in [&mut self.readers, &mut self.waiting] {
+            let mut keep = Vec::with_capacity(holders.len());
+            for holder in holders.drain(..) {
+                match find_stale(&holder) {
+                    Some(reason) => evicted.push((holder, reason)),
+                    None => keep.push(holder),
+                }
+            }
+            *holders = keep;
+        }
+        evicted
+    }
+
+    pub fn describe_holders(&self) -> String {
+        let holders = if let Some(writer) = &self.writer {
+            format!("writer {}", writer.describe())
+        } else if self.readers.is_empty() {
+            "nobody".to_owned()
+        } else {
+            format!(
+                "{} reader(s): {}",
+                self.readers.len(),
+                self.readers.iter().map(|reader| reader.describe()).join(", ")
+            )
+        };
+        if self.waiting.is_empty() {
+            holders
+        } else {
+            format!("{} ({} waiting)", holders, self.waiting.len())
+        }
+    }
+}
+
+impl LockHolder {
+    pub fn current(task: Task) -> Self {
+        let pid = process::id();
+        let now = OffsetDateTime::now_utc();
+        LockHolder {
+            pid,
+            pid_start: process_start_time(pid),
+            acquired: now,
+            heartbeat: now,
+            task,
+        }
+    }
+
+    /// Same process, even if the heartbeat was updated or the lock acquired in the meantime.
+    pub fn is_same(&self, other: &LockHolder) -> bool {
+        self.pid == other.pid && self.pid_start == other.pid_start
+    }
+
+    /// Reason why this holder is believed to have died without releasing the lock, if any.
+    pub fn find_stale_reason(&self) -> Option<String> {
+        let silence = OffsetDateTime::now_utc() - self.heartbeat;
+        if silence > LEASE_DURATION {
+            return Some(format!("no heartbeat for {}s", silence.whole_seconds()));
+        }
+        if !is_process_alive(self.pid) {
+            return Some("process is no longer running".to_owned());
+        }
+        if let Some(expected_start) = &self.pid_start {
+            if process_start_time(self.pid).is_some_and(|start| &start != expected_start) {
+                return Some("process id was reused by a different process".to_owned());
+            }
+        }
+        None
+    }
+
+    pub fn describe(&self) -> String {
+        let held_s = (OffsetDateTime::now_utc() - self.acquired).whole_seconds();
+        format!("#{} for {}s: {}", self.pid, held_s, self.task.as_str())
+    }
+}
+
+pub fn lock_dir() -> Result<PathBuf, String> {
+    let mut pth = dirs::cache_dir().ok_or_else(|| "failed to find cache directory".to_owned())?;
+    pth.push(format!("cmdlock_v{}", DATA_VERSION));
+    create_dir_all(&pth).map_err(|err| {
+        format!(
+            "failed to create lock directory {}, error {}",
+            pth.to_string_lossy(),
+            err
+        )
+    })?;
+    Ok(pth)
+}
+
+pub fn lock_path(key: &str) -> Result<PathBuf, String> {
+    let mut pth = lock_dir()?;
+    pth.push(format!("{}.json", unique_filename(key)));
+    debug!("lock file for key '{}' is at {}", key, pth.to_string_lossy());
+    Ok(pth)
+}
+
+/// All lock files in the lock directory, sorted by key. Unreadable files are skipped with a warning.
+pub fn read_all_lock_files() -> Result<Vec<LockFile>, String> {
+    let dir = lock_dir()?;
+    let entries = fs::read_dir(&dir).map_err(|err| {
+        format!("failed to list lock directory {}, error {}", dir.to_string_lossy(), err)
+    })?;
+    let mut locks = vec![];
+    for entry in entries {
+        let pth = match entry {
+            Ok(entry) => entry.path(),
+            Err(err) => return Err(format!("failed to list lock directory {}, error {}", dir.to_string_lossy(), err)),
+        };
+        if pth.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        match read_lock_file(&pth) {
+            Ok(Some(lock)) => locks.push(lock),
+            Ok(None) => debug!("lock file {} disappeared while listing", pth.to_string_lossy()),
+            Err(err) => warn!("skipping lock: {}", err),
+        }
+    }
+    locks.sort_by(|first, second| first.key.cmp(&second.key));
+    Ok(locks)
+}
+
+pub fn read_lock_file(pth: &Path) -> Result<Option<LockFile>, String> {
+    let content = match fs::read_to_string(pth) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(format!(
+                "failed to read lock file {}, error {}",
+                pth.to_string_lossy(),
+                err
+            ))
+        }
+    };
+    serde_json::from_str(&content).map(Some).map_err(|err| {
+        format!(
+            "failed to parse lock file {} (use --unlock to remove it), error {}",
+            pth.to_string_lossy(),
+            err
+        )
+    })
+}
+
+/// Read, change and write back the lock file, while no other process can change it.
+/// The file is removed if nobody holds the lock afterwards.
+pub fn update_lock_file<T>(
+    pth: &Path,
+    key: &str,
+    update: impl FnOnce(&mut LockFile) -> T,
+) -> Result<T, String> {
+    let _guard = FileGuard::acquire(pth.with_extension("guard"))?;
+    let mut lock = read_lock_file(pth)?.unwrap_or_else(|| LockFile::new(key.to_owned()));
+    let result = update(&mut lock);
+    if lock.is_unused() {
+        remove_lock_file(pth)?;
+    } else {
+        let json = serde_json::to_string_pretty(&lock).map_err(|err| {
+            format!(
+                "failed to serialize lock file for {}, error {}",
+                pth.to_string_lossy(),
+                err
+            )
+        })?;
+        let tmp_pth = pth.with_extension("tmp");
+        fs::write(&tmp_pth, json).map_err(|err| {
+            format!(
+                "failed to write lock file {}, error {}",
+                tmp_pth.to_string_lossy(),
+                err
+            )
+        })?;
+        fs::rename(&tmp_pth, pth).map_err(|err| {
+            format!(
+                "failed to move lock file into place at {}, error {}",
+                pth.to_string_lossy(),
+                err
+            )
+        })?;
+    }
+    Ok(result)
+}
+
+pub fn remove_lock_file(pth: &Path) -> Result<(), String> {
+    match fs::remove_file(pth) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(format!(
+            "failed to remove lock file {}, error {}",
+            pth.to_string_lossy(),
+            err
+        )),
+    }
+}
+
+/// Short-lived exclusive access to the lock file, based on atomic creation of a marker file.
+#[derive(Debug)]
+struct FileGuard {
+    pth: PathBuf,
+}
+
+impl FileGuard {
+    fn acquire(pth: PathBuf) -> Result<Self, String> {
+        let t0 = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&pth) {
+                Ok(_) => return Ok(FileGuard { pth }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => {
+                    return Err(format!(
+                        "failed to create lock guard {}, error {}",
+                        pth.to_string_lossy(),
+                        err
+                    ))
+                }
+            }
+            if is_abandoned(&pth) && remove_abandoned_guard(&pth) {
+                continue;
+            }
+            if t0.elapsed() > 2 * GUARD_ABANDONED_AFTER {
+                return Err(format!(
+                    "could not get access to lock file within {}s, guard file {} is not released",
+                    t0.elapsed().as_secs(),
+                    pth.to_string_lossy()
+                ));
+            }
+            sleep(Duration::from_millis(5));
+        }
+    }
+}
+
+/// Remove the guard if it is still abandoned, returning whether it is gone.
+/// Other processes may find the same abandoned guard, so it is checked again while holding a second guard,
+/// to not remove a new guard that one of them created in the meantime.
+fn remove_abandoned_guard(pth: &Path) -> bool {
+    let evict_pth = pth.with_extension("evict");
+    if OpenOptions::new().write(true).create_new(true).open(&evict_pth).is_err() {
+        if is_abandoned(&evict_pth) {
+            warn!("removing abandoned lock guard {}", evict_pth.to_string_lossy());
+            let _ = fs::remove_file(&evict_pth);
+        }
+        return false;
+    }
+    let is_removed = if is_abandoned(pth) {
+        warn!("removing abandoned lock guard {}", pth.to_string_lossy());
+        let _ = fs::remove_file(pth);
+        true
+    } else {
+        false
+    };
+    let _ = fs::remove_file(&evict_pth);
+    is_removed
+}
+
+fn is_abandoned(pth: &Path) -> bool {
+    fs::metadata(pth)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .map(|age| age > GUARD_ABANDONED_AFTER)
+        .unwrap_or(false)
+}
+
+impl Drop for FileGuard {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.pth) {
+            warn!(
+                "failed to remove lock guard {}, error {}",
+                self.pth.to_string_lossy(),
+                err
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holder(pid: u32) -> LockHolder {
+        let now = OffsetDateTime::now_utc();
+        LockHolder {
+            pid,
+            pid_start: None,
+            acquired: now,
+            heartbeat: now,
+            task: Task::noop(),
+        }
+    }
+
+    #[test]
+    fn many_readers() {
+        let mut lock = LockFile::new("key".to_owned());
+        let (reader1, reader2) = (holder(1), holder(2));
+        assert!(lock.try_acquire(&reader1, true));
+        assert!(lock.try_acquire(&reader2, true));
+        assert!(!lock.try_acquire(&holder(3), false));
+        assert_eq!(lock.readers.len(), 2);
+        assert_eq!(lock.waiting.len(), 1);
+        assert!(lock.release(&reader1));
+        assert!(lock.release(&reader2));
+        assert!(lock.is_free());
+        assert!(lock.try_acquire(&holder(3), false));
+        assert!(lock.waiting.is_empty());
+    }
+
+    #[test]
+    fn one_writer() {
+        let mut lock = LockFile::new("key".to_owned());
+        let writer = holder(1);
+        assert!(lock.try_acquire(&writer, false));
+        assert!(!lock.try_acquire(&holder(2), false));
+        assert!(!lock.try_acquire(&holder(3), true));
+        assert!(!lock.try_acquire(&holder(3), true));
+        assert_eq!(lock.waiting.len(), 2);
+        assert!(lock.release(&holder(2)));
+        assert!(!lock.release(&holder(4)));
+        assert!(lock.release(&writer));
+        assert!(lock.is_free());
+        assert!(!lock.is_unused());
+    }
+
+    #[test]
+    fn release_after_refresh() {
+        let mut lock = LockFile::new("key".to_owned());
+        let writer = holder(1);
+        assert!(lock.try_acquire(&writer, false));
+        let old = writer.heartbeat - LEASE_DURATION;
+        lock.writer.as_mut().unwrap().heartbeat = old;
+        assert!(lock.refresh(&writer));
+        assert!(lock.writer.as_ref().unwrap().heartbeat > old);
+        assert!(lock.release(&writer));
+        assert!(!lock.refresh(&writer));
+    }
+
+    #[test]
+    fn evict_stale_holders() {
+        let mut lock = LockFile::new("key".to_owned());
+        let (reader1, reader2) = (holder(1), holder(2));
+        assert!(lock.try_acquire(&reader1, true));
+        assert!(lock.try_acquire(&reader2, true));
+        assert!(!lock.try_acquire(&holder(3), false));
+        let evicted = lock.evict_stale(|holder| (holder.pid >= 2).then(|| "dead".to_owned()));
+        assert_eq!(evicted.iter().map(|(holder, _)| holder.pid).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(lock.readers.len(), 1);
+        assert!(lock.readers[0].is_same(&reader1));
+        assert!(lock.waiting.is_empty());
+    }
+
+    #[test]
+    fn stale_reasons() {
+        let current = LockHolder::current(Task::noop());
+        assert_eq!(current.find_stale_reason(), None);
+        let expired = LockHolder {
+            heartbeat: current.heartbeat - 2 * LEASE_DURATION,
+            ..current.clone()
+        };
+        assert!(expired.find_stale_reason().unwrap().contains("heartbeat"));
+        let reused = LockHolder {
+            pid_start: Some("other".to_owned()),
+            ..current.clone()
+        };
+        assert!(reused.find_stale_reason().unwrap().contains("reused"));
+    }
+
+    #[test]
+    fn update_removes_free_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let pth = dir.path().join("test.json");
+        let writer = holder(1);
+        assert!(update_lock_file(&pth, "key", |lock| lock.try_acquire(&writer, false)).unwrap());
+        assert_eq!(read_lock_file(&pth).unwrap().unwrap().writer.unwrap().pid, writer.pid);
+        assert!(update_lock_file(&pth, "key", |lock| lock.release(&writer)).unwrap());
+        assert!(!pth.exists());
+        assert!(!pth.with_extension("guard").exists());
+    }
+
+    #[test]
+    fn abandoned_guard_not_removed_while_evicting() {
+        let dir = tempfile::tempdir().unwrap();
+        let guard_pth = dir.path().join("test.guard");
+        let guard = fs::File::create(&guard_pth).unwrap();
+        guard.set_modified(SystemTime::now() - 2 * GUARD_ABANDONED_AFTER).unwrap();
+        fs::write(guard_pth.with_extension("evict"), "").unwrap();
+        assert!(!remove_abandoned_guard(&guard_pth));
+        assert!(guard_pth.exists());
+        fs::remove_file(guard_pth.with_extension("evict")).unwrap();
+        assert!(remove_abandoned_guard(&guard_pth));
+        assert!(!guard_pth.exists());
+        assert!(!guard_pth.with_extension("evict").exists());
+    }
 }
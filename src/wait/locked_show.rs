@@ -0,0 +1,87 @@
+use ::serde::Serialize;
+use ::time::format_description::well_known::Rfc3339;
+use ::time::OffsetDateTime;
+
+use crate::wait::locked_lock::read_all_lock_files;
+use crate::wait::locked_lock::LockFile;
+use crate::wait::locked_lock::LockHolder;
+
+#[derive(Debug, Serialize)]
+struct LockStatus {
+    key: String,
+    writer: Option<HolderStatus>,
+    readers: Vec<HolderStatus>,
+    waiting: Vec<HolderStatus>,
+}
+
+#[derive(Debug, Serialize)]
+struct HolderStatus {
+    pid: u32,
+    task: String,
+    working_dir: String,
+    /// For holders, since when they have the lock, for waiting processes since when they are waiting.
+    since: String,
+    duration_s: i64,
+    stale: Option<String>,
+}
+
+impl LockStatus {
+    fn of(lock: &LockFile) -> Self {
+        LockStatus {
+            key: lock.key.clone(),
+            writer: lock.writer.as_ref().map(HolderStatus::of),
+            readers: lock.readers.iter().map(HolderStatus::of).collect(),
+            waiting: lock.waiting.iter().map(HolderStatus::of).collect(),
+        }
+    }
+}
+
+impl HolderStatus {
+    fn of(holder: &LockHolder) -> Self {
+        HolderStatus {
+            pid: holder.pid,
+            task: holder.task.as_str(),
+            working_dir: holder.task.working_dir.to_string_lossy().into_owned(),
+            since: holder.acquired.format(&Rfc3339).expect("failed to format time"),
+            duration_s: (OffsetDateTime::now_utc() - holder.acquired).whole_seconds(),
+            stale: holder.find_stale_reason(),
+        }
+    }
+
+    fn describe(&self, role: &str) -> String {
+        let stale = match &self.stale {
+            Some(reason) => format!(" [stale: {}]", reason),
+            None => "".to_owned(),
+        };
+        format!("  {} #{} for {}s: {}{}", role, self.pid, self.duration_s, self.task, stale)
+    }
+}
+
+/// Show who holds and who waits for each lock, as text or json lines.
+pub fn show_all_locks(json: bool) -> Result<Vec<String>, String> {
+    let locks = read_all_lock_files()?
+        .iter()
+        .map(LockStatus::of)
+        .collect::<Vec<_>>();
+    if json {
+        let json = serde_json::to_string_pretty(&locks)
+            .map_err(|err| format!("failed to serialize lock status, error {}", err))?;
+        return Ok(vec![json]);
+    }
+    if locks.is_empty() {
+        return Ok(vec!["no locks are held".to_owned()]);
+    }
+    let mut lines = vec![];
+    for lock in locks {
+        lines.push(format!(
+            "lock '{}' ({} holder(s), {} waiting)",
+            lock.key,
+            lock.writer.iter().count() + lock.readers.len(),
+            lock.waiting.len()
+        ));
+        lines.extend(lock.writer.iter().map(|writer| writer.describe("writer")));
+        lines.extend(lock.readers.iter().map(|reader| reader.describe("reader")));
+        lines.extend(lock.waiting.iter().map(|waiter| waiter.describe("waiting")));
+    }
+    Ok(lines)
+}
@@ -1,6 +1,6 @@
 use ::clap::Parser;
 
-use ::rusht::wait::locked;
+use ::rusht::wait::handle_locked;
 use ::rusht::wait::LockedArgs;
 use ::rusht::ExitStatus;
 
@@ -9,11 +9,5 @@ fn main() -> ExitStatus {
         env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn"),
     );
     let args = LockedArgs::parse();
-    match locked(args) {
-        Ok(()) => ExitStatus::ok(),
-        Err(err) => {
-            eprintln!("failed: {}", err);
-            ExitStatus::err()
-        }
-    }
+    handle_locked(args)
 }
@@ -0,0 +1,13 @@
+use ::clap::Parser;
+
+use ::rusht::wait::handle_wait_for;
+use ::rusht::wait::WaitForArgs;
+use ::rusht::ExitStatus;
+
+fn main() -> ExitStatus {
+    env_logger::init_from_env(
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn"),
+    );
+    let args = WaitForArgs::parse();
+    handle_wait_for(args)
+}
@@ -1 +1,246 @@
+use ::std::cmp::min;
+use ::std::env;
+use ::std::fs;
+use ::std::net::TcpStream;
+use ::std::net::ToSocketAddrs;
+use ::std::path::Path;
+use ::std::path::PathBuf;
+use ::std::thread::sleep;
+use ::std::time::Duration;
+use ::std::time::Instant;
+use ::std::time::SystemTime;
 
+use ::async_std::task::block_on;
+use ::itertools::Itertools;
+use ::log::debug;
+
+use crate::common::DiscardWriter;
+use crate::common::Task;
+use crate::common::Timeout;
+use crate::wait::WaitForArgs;
+use crate::ExitStatus;
+
+const MIN_CHECK_INTERVAL: Duration = Duration::from_millis(20);
+const MAX_CHECK_INTERVAL: Duration = Duration::from_millis(1000);
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const CHECK_STOP_GRACE: Duration = Duration::from_secs(1);
+
+pub fn wait_for(args: WaitForArgs) -> Result<ExitStatus, String> {
+    let mut conditions = conditions_from_args(&args);
+    wait_until_met(&mut conditions, args.timeout, args.show_progress)?;
+    Ok(match args.cmd {
+        Some(cmd) => cmd.into_task().execute_sync(true),
+        None => ExitStatus::ok(),
+    })
+}
+
+#[derive(Debug)]
+enum Condition {
+    Port(String),
+    Socket(PathBuf),
+    File {
+        path: PathBuf,
+        stable: Option<Duration>,
+        last_change: Option<(u64, SystemTime, Instant)>,
+    },
+    Command {
+        check: String,
+        task: Task,
+    },
+}
+
+fn conditions_from_args(args: &WaitForArgs) -> Vec<Condition> {
+    let mut conditions = vec![];
+    for port in &args.ports {
+        let addr = if port.contains(':') {
+            port.to_owned()
+        } else {
+            format!("localhost:{}", port)
+        };
+        conditions.push(Condition::Port(addr));
+    }
+    for socket in &args.sockets {
+        conditions.push(Condition::Socket(socket.to_owned()));
+    }
+    for file in &args.files {
+        conditions.push(Condition::File {
+            path: file.to_owned(),
+            stable: args.stable,
+            last_change: None,
+        });
+    }
+    for check in &args.checks {
+        conditions.push(command_condition(check));
+    }
+    conditions
+}
+
+fn command_condition(check: &str) -> Condition {
+    let cwd = env::current_dir().expect("could not determine working directory");
+    Condition::Command {
+        check: check.to_owned(),
+        task: Task::new("sh".to_owned(), vec!["-c".to_owned(), check.to_owned()], cwd, None),
+    }
+}
+
+impl Condition {
+    /// Check the condition once, taking at most about `remaining` (the time left until the overall timeout).
+    fn is_met(&mut self, remaining: Duration) -> bool {
+        match self {
+            Condition::Port(addr) => is_port_open(addr, remaining),
+            Condition::Socket(path) => is_socket(path),
+            Condition::File { path, stable, last_change } => {
+                let Ok(meta) = fs::metadata(&*path) else {
+                    *last_change = None;
+                    return false;
+                };
+                let Some(stable) = stable else {
+                    return true;
+                };
+                let current = (meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+                match last_change {
+                    Some((len, modified, since)) if (*len, *modified) == current => since.elapsed() >= *stable,
+                    _ => {
+                        debug!("file {} changed, waiting for it to be stable", path.to_string_lossy());
+                        *last_change = Some((current.0, current.1, Instant::now()));
+                        false
+                    }
+                }
+            }
+            Condition::Command { task, .. } => {
+                let timeout = Timeout { limit: remaining.max(MIN_CHECK_INTERVAL), grace: CHECK_STOP_GRACE };
+                let task = task.clone().with_timeout(Some(timeout));
+                let status = block_on(task.execute_with_outerr(false, &mut DiscardWriter::new(), &mut DiscardWriter::new()));
+                status.is_ok()
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Condition::Port(addr) => format!("port {}", addr),
+            Condition::Socket(path) => format!("socket {}", path.to_string_lossy()),
+            Condition::File { path, stable: None, .. } => format!("file {}", path.to_string_lossy()),
+            Condition::File { path, stable: Some(stable), .. } =>
+                format!("file {} (stable for {} ms)", path.to_string_lossy(), stable.as_millis()),
+            Condition::Command { check, .. } => format!("command '{}'", check),
+        }
+    }
+}
+
+fn is_port_open(addr: &str, remaining: Duration) -> bool {
+    let connect_timeout = remaining.min(CONNECT_TIMEOUT).max(MIN_CHECK_INTERVAL);
+    let socket_addrs = match addr.to_socket_addrs() {
+        Ok(socket_addrs) => socket_addrs,
+        Err(err) => {
+            debug!("could not resolve address {}, err {}", addr, err);
+            return false;
+        }
+    };
+    for socket_addr in socket_addrs {
+        if TcpStream::connect_timeout(&socket_addr, connect_timeout).is_ok() {
+            debug!("connected to {} ({})", addr, socket_addr);
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(unix)]
+fn is_socket(path: &Path) -> bool {
+    use ::std::os::unix::fs::FileTypeExt;
+    fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket())
+}
+
+#[cfg(not(unix))]
+fn is_socket(path: &Path) -> bool {
+    path.exists()
+}
+
+/// Check all conditions until they are met, checking less frequently over time. Conditions that were met once are not checked again.
+fn wait_until_met(conditions: &mut Vec<Condition>, timeout: Duration, show_progress: bool) -> Result<(), String> {
+    let t0 = Instant::now();
+    let mut interval = MIN_CHECK_INTERVAL;
+    let mut last_progress: Option<(String, Instant)> = None;
+    loop {
+        conditions.retain_mut(|condition| {
+            let is_met = condition.is_met(timeout.saturating_sub(t0.elapsed()));
+            if is_met {
+                debug!("{} is available after {} ms", condition.describe(), t0.elapsed().as_millis());
+            }
+            !is_met
+        });
+        if conditions.is_empty() {
+            return Ok(());
+        }
+        let pending = conditions.iter().map(|condition| condition.describe()).join(", ");
+        if t0.elapsed() >= timeout {
+            return Err(format!("timed out after {}s waiting for {}", t0.elapsed().as_secs(), pending));
+        }
+        if show_progress {
+            let should_show = match &last_progress {
+                Some((prev, at)) => prev != &pending || at.elapsed() >= PROGRESS_INTERVAL,
+                None => true,
+            };
+            if should_show {
+                eprintln!("waiting for {} (checking every {} ms, waited {}s)", pending, interval.as_millis(), t0.elapsed().as_secs());
+                last_progress = Some((pending, Instant::now()));
+            }
+        }
+        sleep(min(interval, timeout.saturating_sub(t0.elapsed())));
+        interval = min(interval * 2, MAX_CHECK_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn open_and_closed_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(Condition::Port(format!("127.0.0.1:{}", port)).is_met(Duration::from_secs(1)));
+        drop(listener);
+        assert!(!Condition::Port(format!("127.0.0.1:{}", port)).is_met(Duration::from_secs(1)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.sock");
+        assert!(!Condition::Socket(path.clone()).is_met(Duration::from_secs(1)));
+        let _listener = ::std::os::unix::net::UnixListener::bind(&path).unwrap();
+        assert!(Condition::Socket(path).is_met(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn file_appears_and_is_stable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let mut condition = Condition::File { path: path.clone(), stable: Some(Duration::from_millis(30)), last_change: None };
+        assert!(!condition.is_met(Duration::from_secs(1)));
+        fs::write(&path, "data").unwrap();
+        assert!(!condition.is_met(Duration::from_secs(1)));
+        sleep(Duration::from_millis(40));
+        assert!(condition.is_met(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn command_and_timeout() {
+        assert!(wait_until_met(&mut vec![command_condition("true")], Duration::from_secs(5), false).is_ok());
+        let err = wait_until_met(&mut vec![command_condition("false")], Duration::from_millis(50), false).unwrap_err();
+        assert!(err.contains("command 'false'"), "{}", err);
+    }
+
+    #[test]
+    fn hanging_command_stops_at_timeout() {
+        let t0 = Instant::now();
+        assert!(wait_until_met(&mut vec![command_condition("sleep 30")], Duration::from_millis(200), false).is_err());
+        assert!(t0.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,50 @@
+use ::std::path::PathBuf;
+use ::std::time::Duration;
+
+use ::clap::Parser;
+use ::parse_duration0::parse as parse_dur;
+
+use crate::common::CommandArgs;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "wait_for",
+    about = "Wait until a port, socket or file is available, or a command succeeds, then optionally run a command.",
+    after_help = "If multiple conditions are given, all of them should be met.",
+    group = clap::ArgGroup::new("condition").multiple(true).required(true),
+)]
+pub struct WaitForArgs {
+    #[arg(short = 'P', long = "port", group = "condition")]
+    /// Wait until this TCP port accepts connections. Either a port number on localhost, or host:port.
+    pub ports: Vec<String>,
+    #[arg(short = 'S', long = "socket", group = "condition")]
+    /// Wait until this Unix socket exists.
+    pub sockets: Vec<PathBuf>,
+    #[arg(short = 'F', long = "file", group = "condition")]
+    /// Wait until this file exists.
+    pub files: Vec<PathBuf>,
+    #[arg(value_parser = parse_dur, short = 's', long = "stable", requires = "files")]
+    /// With --file, also wait until the file's size and modification time did not change for this duration, e.g. "2s".
+    pub stable: Option<Duration>,
+    #[arg(short = 'c', long = "check", group = "condition")]
+    /// Wait until this shell command succeeds (exit code 0). Its output is discarded.
+    pub checks: Vec<String>,
+    #[arg(value_parser = parse_dur, short = 't', long = "timeout", default_value = "5 min")]
+    /// Duration after which the waiting stops and the command fails. E.g. \"30 s\" or \"1 min\".
+    pub timeout: Duration,
+    #[arg(short = 'p', long = "progress")]
+    /// Show an indicator that we are still waiting, and for which conditions.
+    pub show_progress: bool,
+    #[command(subcommand)]
+    /// Command to run after all conditions are met.
+    pub cmd: Option<CommandArgs>,
+}
+
+#[test]
+fn test_cli_args() {
+    WaitForArgs::try_parse_from(&["cmd", "-P", "8080", "-P=db:5432", "-t=30s", "ls"]).unwrap();
+    WaitForArgs::try_parse_from(&["cmd", "-F", "out.log", "--stable", "2s", "-p"]).unwrap();
+    WaitForArgs::try_parse_from(&["cmd", "--socket", "/tmp/x.sock", "--check", "curl localhost"]).unwrap();
+    assert!(WaitForArgs::try_parse_from(&["cmd", "ls"]).is_err());
+    assert!(WaitForArgs::try_parse_from(&["cmd", "-P", "80", "--stable", "2s"]).is_err());
+}
@@ -0,0 +1,182 @@
+
+// auto-generated by build.rs
+#[cfg(test)]
+mod generated_tests {
+	use ::std::process::Command;
+
+	#[test]
+	fn bin_batched_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "batched", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary batched");
+		assert_eq!(out.status.code(), Some(0), "binary batched --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_between_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "between", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary between");
+		assert_eq!(out.status.code(), Some(0), "binary between --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_cached_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "cached", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary cached");
+		assert_eq!(out.status.code(), Some(0), "binary cached --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_cmadd_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "cmadd", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary cmadd");
+		assert_eq!(out.status.code(), Some(0), "binary cmadd --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_cmbuf_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "cmbuf", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary cmbuf");
+		assert_eq!(out.status.code(), Some(0), "binary cmbuf --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_cmdo_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "cmdo", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary cmdo");
+		assert_eq!(out.status.code(), Some(0), "binary cmdo --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_cmdrop_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "cmdrop", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary cmdrop");
+		assert_eq!(out.status.code(), Some(0), "binary cmdrop --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_cmlist_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "cmlist", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary cmlist");
+		assert_eq!(out.status.code(), Some(0), "binary cmlist --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_dir_with_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "dir_with", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary dir_with");
+		assert_eq!(out.status.code(), Some(0), "binary dir_with --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_files_with_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "files_with", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary files_with");
+		assert_eq!(out.status.code(), Some(0), "binary files_with --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_filter_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "filter", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary filter");
+		assert_eq!(out.status.code(), Some(0), "binary filter --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_grab_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "grab", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary grab");
+		assert_eq!(out.status.code(), Some(0), "binary grab --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_jl_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "jl", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary jl");
+		assert_eq!(out.status.code(), Some(0), "binary jl --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_locked_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "locked", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary locked");
+		assert_eq!(out.status.code(), Some(0), "binary locked --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_mon_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "mon", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary mon");
+		assert_eq!(out.status.code(), Some(0), "binary mon --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_mvnw_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "mvnw", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary mvnw");
+		assert_eq!(out.status.code(), Some(0), "binary mvnw --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_namesafe_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "namesafe", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary namesafe");
+		assert_eq!(out.status.code(), Some(0), "binary namesafe --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_piped_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "piped", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary piped");
+		assert_eq!(out.status.code(), Some(0), "binary piped --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_pomp_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "pomp", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary pomp");
+		assert_eq!(out.status.code(), Some(0), "binary pomp --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_rsh_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "rsh", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary rsh");
+		assert_eq!(out.status.code(), Some(0), "binary rsh --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_rusht_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "rusht", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary rusht");
+		assert_eq!(out.status.code(), Some(0), "binary rusht --help exit code was not 0");
+	}
+
+	#[test]
+	fn bin_unique_help() {
+		
+		let out = Command::new("cargo").args(["run", "--bin", "unique", "--all-features", "--", "--help"])
+			.output().expect("failed to execute binary unique");
+		assert_eq!(out.status.code(), Some(0), "binary unique --help exit code was not 0");
+	}
+}